@@ -181,7 +181,9 @@ impl Executor {
         )
         .context("create arrow writer")?;
         let mut num_rows = 0;
-        // TODO: support multi-part write
+        // Row groups are flushed to the object writer once they are full, which
+        // switches to multipart upload when buffered data exceeds its capacity, so
+        // the whole file is never buffered in memory.
         while let Some(batch) = stream.next().await {
             let batch = batch.context("execute plan")?;
             num_rows += batch.num_rows();
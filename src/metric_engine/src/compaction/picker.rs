@@ -97,11 +97,24 @@ impl TimeWindowCompactionStrategy {
             return None;
         }
 
+        // Files may be claimed by others(e.g. purge) since they are found, so
+        // claim them atomically.
+        let mut claimed: Vec<&SstFile> = Vec::with_capacity(compaction_files.len());
         for f in &compaction_files {
-            f.mark_compaction();
+            if !f.try_mark_compaction() {
+                for claimed_file in claimed {
+                    claimed_file.unmark_compaction();
+                }
+                return None;
+            }
+            claimed.push(f);
         }
-        for f in &expired_files {
-            f.mark_compaction();
+        let expired_files = expired_files
+            .into_iter()
+            .filter(|f| f.try_mark_compaction())
+            .collect::<Vec<_>>();
+        if compaction_files.is_empty() && expired_files.is_empty() {
+            return None;
         }
 
         let task = Task {
@@ -17,7 +17,11 @@
 
 use std::{collections::HashMap, time::Duration};
 
-use parquet::basic::{Compression, Encoding, ZstdLevel};
+use object_store::OBJECT_STORE_COALESCE_DEFAULT;
+use parquet::{
+    basic::{Compression, Encoding, ZstdLevel},
+    file::properties::EnabledStatistics,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -58,6 +62,10 @@ pub enum ParquetEncoding {
     DeltaLengthByteArray,
     DeltaByteArray,
     RleDictionary,
+    /// Splits bytes of each value into separate streams, which compresses
+    /// float values much better. Only supported for fixed width columns,
+    /// i.e. integers, floats, decimals, temporals and fixed size binary.
+    ByteStreamSplit,
 }
 
 impl From<ParquetEncoding> for Encoding {
@@ -69,6 +77,7 @@ impl From<ParquetEncoding> for Encoding {
             ParquetEncoding::DeltaLengthByteArray => Encoding::DELTA_LENGTH_BYTE_ARRAY,
             ParquetEncoding::DeltaByteArray => Encoding::DELTA_BYTE_ARRAY,
             ParquetEncoding::RleDictionary => Encoding::RLE_DICTIONARY,
+            ParquetEncoding::ByteStreamSplit => Encoding::BYTE_STREAM_SPLIT,
         }
     }
 }
@@ -92,6 +101,25 @@ impl From<ParquetCompression> for Compression {
     }
 }
 
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub enum ParquetStatistics {
+    None,
+    Chunk,
+    #[default]
+    Page,
+}
+
+impl From<ParquetStatistics> for EnabledStatistics {
+    fn from(value: ParquetStatistics) -> Self {
+        match value {
+            ParquetStatistics::None => EnabledStatistics::None,
+            ParquetStatistics::Chunk => EnabledStatistics::Chunk,
+            ParquetStatistics::Page => EnabledStatistics::Page,
+        }
+    }
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct ColumnOptions {
@@ -99,12 +127,14 @@ pub struct ColumnOptions {
     pub enable_bloom_filter: Option<bool>,
     pub encoding: Option<ParquetEncoding>,
     pub compression: Option<ParquetCompression>,
+    pub statistics: Option<ParquetStatistics>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct WriteConfig {
     pub max_row_group_size: usize,
+    pub data_page_size_limit: usize,
     pub write_bacth_size: usize,
     pub enable_sorting_columns: bool,
     // use to set column props with default value
@@ -112,6 +142,7 @@ pub struct WriteConfig {
     pub enable_bloom_filter: bool,
     pub encoding: ParquetEncoding,
     pub compression: ParquetCompression,
+    pub statistics: ParquetStatistics,
     // use to set column props with column name
     pub column_options: Option<HashMap<String, ColumnOptions>>,
 }
@@ -120,17 +151,53 @@ impl Default for WriteConfig {
     fn default() -> Self {
         Self {
             max_row_group_size: 8192,
+            data_page_size_limit: 1024 * 1024,
             write_bacth_size: 1024,
             enable_sorting_columns: true,
             enable_dict: false,
             enable_bloom_filter: false,
             encoding: ParquetEncoding::Plain,
             compression: ParquetCompression::Snappy,
+            statistics: ParquetStatistics::Page,
             column_options: None,
         }
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReadConfig {
+    /// Byte ranges of a SST whose gap is less than this are fetched in one
+    /// request.
+    ///
+    /// With the default value, ranges are fetched by the object store's own
+    /// `get_ranges`, so stores overriding it (e.g. local file system) keep
+    /// their behavior. Other values apply to all stores, each merged range
+    /// is fetched by a separate `get_range`.
+    pub range_coalesce_gap: usize,
+    /// Evaluate predicates while decoding SSTs, which avoids decoding other
+    /// columns of rows filtered out. Only predicates on primary keys are
+    /// evaluated before dedup, except in keep-all mode.
+    pub enable_pushdown_filters: bool,
+    /// Scans whose time range is wider than this are rejected, `None` means
+    /// no limit.
+    pub max_scan_time_range: Option<Duration>,
+    /// Scans whose total size of SSTs to read exceeds this are rejected,
+    /// `None` means no limit.
+    pub max_scan_bytes: Option<u64>,
+}
+
+impl Default for ReadConfig {
+    fn default() -> Self {
+        Self {
+            range_coalesce_gap: OBJECT_STORE_COALESCE_DEFAULT,
+            enable_pushdown_filters: false,
+            max_scan_time_range: None,
+            max_scan_bytes: None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct ManifestConfig {
@@ -157,15 +224,29 @@ impl Default for ManifestConfig {
 #[serde(default, deny_unknown_fields)]
 pub struct StorageConfig {
     pub write: WriteConfig,
+    pub read: ReadConfig,
     pub manifest: ManifestConfig,
     pub scheduler: SchedulerConfig,
     pub update_mode: UpdateMode,
+    /// Writes are rejected once total size of SSTs reaches this, `None` means
+    /// no limit.
+    ///
+    /// This is a soft limit: it's checked before each write without reserving
+    /// the write's size, so concurrent writes may overshoot it.
+    pub size_quota: Option<u64>,
 }
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub enum UpdateMode {
+    /// Rows with the same primary keys are deduplicated, only the latest one
+    /// is kept.
     #[default]
     Overwrite,
+    /// Values of rows with the same primary keys are appended together, so
+    /// all value columns must be `Binary`.
     Append,
+    /// Rows with the same primary keys are all kept, so no merging is done
+    /// when reading, which suits append-only data.
+    KeepAll,
 }
@@ -120,6 +120,13 @@ impl SnapshotHeader {
             "invalid bytes to convert to header."
         );
         let version = reader.read_u8().context("read snapshot header version")?;
+        // Snapshot written by a newer version may use a record layout we don't
+        // know, so refuse to read it instead of misinterpreting the records.
+        ensure!(
+            version <= SnapshotRecord::VERSION,
+            "unsupported snapshot version, version:{version}, max_supported:{}",
+            SnapshotRecord::VERSION
+        );
         let flag = reader.read_u8().context("read snapshot header flag")?;
         let length = reader
             .read_u64::<LittleEndian>()
@@ -363,6 +370,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_snapshot_header_newer_version() {
+        let header = SnapshotHeader {
+            version: SnapshotRecord::VERSION + 1,
+            ..SnapshotHeader::new()
+        };
+        let mut vec = vec![0u8; SnapshotHeader::LENGTH];
+        header.write_to(vec.as_mut_slice()).unwrap();
+        assert!(SnapshotHeader::try_new(Cursor::new(vec)).is_err());
+    }
+
     #[test]
     fn test_snapshot_record() {
         let sstfile = SstFile::new(
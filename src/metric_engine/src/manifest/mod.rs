@@ -40,14 +40,16 @@ use tracing::{debug, error, info, trace};
 
 use crate::{
     config::ManifestConfig,
+    ensure,
     sst::{FileId, FileMeta, SstFile},
-    types::{ObjectStoreRef, RuntimeRef, TimeRange},
+    types::{ObjectStoreRef, RuntimeRef, TimeRange, Timestamp},
     AnyhowError, Result,
 };
 
 pub const PREFIX_PATH: &str = "manifest";
 pub const SNAPSHOT_FILENAME: &str = "snapshot";
 pub const DELTA_PREFIX: &str = "delta";
+pub const PURGE_CUTOFF_FILENAME: &str = "purge_cutoff";
 
 // Used for manifest delta filename
 // This number mustn't go backwards on restarts, otherwise file id
@@ -70,6 +72,12 @@ pub struct Manifest {
     merger: Arc<ManifestMerger>,
 
     ssts: RwLock<Vec<SstFile>>,
+    /// Total size of `ssts`, kept so it's cheap to read.
+    total_size: AtomicU64,
+
+    purge_cutoff_path: Path,
+    /// Data before this are purged, `None` when never purged.
+    purge_cutoff: RwLock<Option<Timestamp>>,
 }
 
 impl Manifest {
@@ -81,6 +89,8 @@ impl Manifest {
     ) -> Result<Self> {
         let snapshot_path = Path::from(format!("{root_dir}/{PREFIX_PATH}/{SNAPSHOT_FILENAME}"));
         let delta_dir = Path::from(format!("{root_dir}/{PREFIX_PATH}/{DELTA_PREFIX}"));
+        let purge_cutoff_path =
+            Path::from(format!("{root_dir}/{PREFIX_PATH}/{PURGE_CUTOFF_FILENAME}"));
 
         let merger = ManifestMerger::try_new(
             snapshot_path.clone(),
@@ -104,11 +114,17 @@ impl Manifest {
             });
         }
 
+        let purge_cutoff = read_purge_cutoff(&store, &purge_cutoff_path).await?;
+
+        let total_size = ssts.iter().map(|f| f.size() as u64).sum();
         Ok(Self {
             delta_dir,
             store,
             merger,
             ssts: RwLock::new(ssts),
+            total_size: AtomicU64::new(total_size),
+            purge_cutoff_path,
+            purge_cutoff: RwLock::new(purge_cutoff),
         })
     }
 
@@ -145,12 +161,24 @@ impl Manifest {
         // 2. Update cached payload
         {
             let mut ssts = self.ssts.write().await;
+            let mut added_size = 0;
             for file in update.to_adds {
+                added_size += file.size() as u64;
                 ssts.push(file);
             }
             // TODO: sort files in payload, so we can delete files more
             // efficiently.
-            ssts.retain(|file| !update.to_deletes.contains(&file.id()));
+            let mut deleted_size = 0;
+            ssts.retain(|file| {
+                let deleted = update.to_deletes.contains(&file.id());
+                if deleted {
+                    deleted_size += file.size() as u64;
+                }
+                !deleted
+            });
+            // Updated under the write lock, so the size matches `ssts`.
+            self.total_size.fetch_add(added_size, Ordering::Relaxed);
+            self.total_size.fetch_sub(deleted_size, Ordering::Relaxed);
         }
 
         Ok(())
@@ -171,6 +199,51 @@ impl Manifest {
             .collect()
     }
 
+    pub fn total_size(&self) -> u64 {
+        self.total_size.load(Ordering::Relaxed)
+    }
+
+    /// Returns the time range covering all SSTs, `None` when there is no SST.
+    pub async fn time_range(&self) -> Option<TimeRange> {
+        let ssts = self.ssts.read().await;
+        let mut iter = ssts.iter();
+        let mut time_range = iter.next()?.meta().time_range.clone();
+        for f in iter {
+            time_range.merge(&f.meta().time_range);
+        }
+
+        Some(time_range)
+    }
+
+    pub async fn purge_cutoff(&self) -> Option<Timestamp> {
+        *self.purge_cutoff.read().await
+    }
+
+    /// Persists `cutoff` as the new purge cutoff, it's ignored when not later
+    /// than the current one.
+    pub async fn set_purge_cutoff(&self, cutoff: Timestamp) -> Result<()> {
+        let mut purge_cutoff = self.purge_cutoff.write().await;
+        if purge_cutoff.is_some_and(|v| v >= cutoff) {
+            return Ok(());
+        }
+
+        self.store
+            .put(
+                &self.purge_cutoff_path,
+                PutPayload::from(cutoff.0.to_le_bytes().to_vec()),
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to write purge cutoff, path:{}",
+                    self.purge_cutoff_path
+                )
+            })?;
+        *purge_cutoff = Some(cutoff);
+
+        Ok(())
+    }
+
     fn allocate_id() -> u64 {
         NEXT_ID.fetch_add(1, Ordering::SeqCst)
     }
@@ -353,6 +426,28 @@ async fn read_snapshot(store: &ObjectStoreRef, path: &Path) -> Result<Snapshot>
     }
 }
 
+async fn read_purge_cutoff(store: &ObjectStoreRef, path: &Path) -> Result<Option<Timestamp>> {
+    let bytes = match store.get(path).await {
+        Ok(v) => v
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to read purge cutoff, path:{path}"))?,
+        Err(object_store::Error::NotFound { .. }) => return Ok(None),
+        Err(err) => {
+            let context = format!("Failed to read purge cutoff, path:{path}");
+            return Err(AnyhowError::new(err).context(context).into());
+        }
+    };
+    ensure!(
+        bytes.len() == 8,
+        "invalid purge cutoff, path:{path}, length:{}",
+        bytes.len()
+    );
+    let cutoff = i64::from_le_bytes(bytes[..].try_into().unwrap());
+
+    Ok(Some(Timestamp(cutoff)))
+}
+
 async fn read_delta_file(store: &ObjectStoreRef, sst_path: &Path) -> Result<ManifestUpdate> {
     let bytes = store
         .get(sst_path)
@@ -450,6 +545,15 @@ mod tests {
             expected_ssts.sort_by_key(|a| a.id());
             ssts.sort_by_key(|a| a.id());
             assert_eq!(expected_ssts, ssts);
+
+            assert_eq!(Some(TimeRange::from(0..20)), manifest.time_range().await);
+
+            assert_eq!((0..20).sum::<u64>(), manifest.total_size());
+            manifest
+                .update(ManifestUpdate::new(Vec::new(), (0..10).collect()))
+                .await
+                .unwrap();
+            assert_eq!((10..20).sum::<u64>(), manifest.total_size());
         });
     }
 
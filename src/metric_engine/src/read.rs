@@ -15,7 +15,7 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::{any::Any, pin::Pin, sync::Arc, task::Poll};
+use std::{any::Any, ops::Range, pin::Pin, sync::Arc, task::Poll};
 
 use anyhow::Context;
 use arrow::{
@@ -27,8 +27,9 @@ use arrow::{
     },
 };
 use arrow_schema::SchemaRef;
+use bytes::Bytes;
 use datafusion::{
-    common::{internal_err, DFSchema},
+    common::{internal_err, stats::Precision, DFSchema, Statistics},
     datasource::{
         listing::PartitionedFile,
         physical_plan::{FileMeta, FileScanConfig, ParquetExec, ParquetFileReaderFactory},
@@ -40,22 +41,32 @@ use datafusion::{
     },
     logical_expr::utils::conjunction,
     parquet::arrow::async_reader::AsyncFileReader,
-    physical_expr::{create_physical_expr, LexOrdering},
+    physical_expr::{
+        create_physical_expr, equivalence::ProjectionMapping, expressions::Column, LexOrdering,
+        PhysicalExpr,
+    },
     physical_plan::{
-        filter::FilterExec, metrics::ExecutionPlanMetricsSet,
-        sorts::sort_preserving_merge::SortPreservingMergeExec, DisplayAs, Distribution,
-        ExecutionPlan, PlanProperties,
+        filter::FilterExec,
+        metrics::{BaselineMetrics, ExecutionPlanMetricsSet, MetricsSet},
+        projection::ProjectionExec,
+        sorts::sort_preserving_merge::SortPreservingMergeExec,
+        DisplayAs, Distribution, ExecutionPlan, PlanProperties,
     },
     physical_planner::create_physical_sort_exprs,
     prelude::{ident, Expr},
 };
-use futures::{Stream, StreamExt};
+use futures::{future::BoxFuture, FutureExt, Stream, StreamExt};
 use itertools::Itertools;
-use parquet::arrow::async_reader::ParquetObjectReader;
+use object_store::{coalesce_ranges, path::Path, OBJECT_STORE_COALESCE_DEFAULT};
+use parquet::{
+    arrow::async_reader::ParquetObjectReader,
+    errors::{ParquetError, Result as ParquetResult},
+    file::metadata::ParquetMetaData,
+};
 
 use crate::{
     compare_primitive_columns,
-    config::UpdateMode,
+    config::{ReadConfig, UpdateMode},
     operator::{BytesMergeOperator, LastValueOperator, MergeOperator, MergeOperatorRef},
     sst::{SstFile, SstPathGenerator},
     types::{ObjectStoreRef, StorageSchema, SEQ_COLUMN_NAME},
@@ -65,12 +76,16 @@ use crate::{
 #[derive(Debug, Clone)]
 pub struct DefaultParquetFileReaderFactory {
     object_store: ObjectStoreRef,
+    range_coalesce_gap: usize,
 }
 
 /// Returns a AsyncFileReader factory
 impl DefaultParquetFileReaderFactory {
-    pub fn new(object_store: ObjectStoreRef) -> Self {
-        Self { object_store }
+    pub fn new(object_store: ObjectStoreRef, range_coalesce_gap: usize) -> Self {
+        Self {
+            object_store,
+            range_coalesce_gap,
+        }
     }
 }
 
@@ -83,11 +98,62 @@ impl ParquetFileReaderFactory for DefaultParquetFileReaderFactory {
         _metrics: &ExecutionPlanMetricsSet,
     ) -> DfResult<Box<dyn AsyncFileReader + Send>> {
         let object_store = self.object_store.clone();
-        let mut reader = ParquetObjectReader::new(object_store, file_meta.object_meta);
+        let location = file_meta.object_meta.location.clone();
+        let mut reader = ParquetObjectReader::new(object_store.clone(), file_meta.object_meta);
         if let Some(size) = metadata_size_hint {
             reader = reader.with_footer_size_hint(size);
         }
-        Ok(Box::new(reader))
+        Ok(Box::new(CoalescedRangeReader {
+            inner: reader,
+            object_store,
+            location,
+            range_coalesce_gap: self.range_coalesce_gap,
+        }))
+    }
+}
+
+/// AsyncFileReader which merges byte ranges whose gap is less than
+/// `range_coalesce_gap` into one request, so that fewer requests are sent
+/// to the object store when fetching column chunks.
+struct CoalescedRangeReader {
+    inner: ParquetObjectReader,
+    object_store: ObjectStoreRef,
+    location: Path,
+    range_coalesce_gap: usize,
+}
+
+impl AsyncFileReader for CoalescedRangeReader {
+    fn get_bytes(&mut self, range: Range<usize>) -> BoxFuture<'_, ParquetResult<Bytes>> {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<usize>>,
+    ) -> BoxFuture<'_, ParquetResult<Vec<Bytes>>> {
+        // Keep using the store's own `get_ranges` for the default gap, since
+        // some stores (e.g. local file system) read ranges more efficiently.
+        if self.range_coalesce_gap == OBJECT_STORE_COALESCE_DEFAULT {
+            return self.inner.get_byte_ranges(ranges);
+        }
+
+        let object_store = &self.object_store;
+        let location = &self.location;
+        let range_coalesce_gap = self.range_coalesce_gap;
+        async move {
+            coalesce_ranges(
+                &ranges,
+                |range| object_store.get_range(location, range),
+                range_coalesce_gap,
+            )
+            .await
+            .map_err(|e| ParquetError::External(Box::new(e)))
+        }
+        .boxed()
+    }
+
+    fn get_metadata(&mut self) -> BoxFuture<'_, ParquetResult<Arc<ParquetMetaData>>> {
+        self.inner.get_metadata()
     }
 }
 
@@ -105,21 +171,44 @@ pub(crate) struct MergeExec {
     seq_idx: usize,
     /// Operator to merge values when primary keys are the same
     value_operator: Arc<dyn MergeOperator>,
+    /// Properties of output, which doesn't contain seq column
+    properties: PlanProperties,
+    /// Execution metrics
+    metrics: ExecutionPlanMetricsSet,
 }
 
 impl MergeExec {
-    pub fn new(
+    pub fn try_new(
         input: Arc<dyn ExecutionPlan>,
         num_primary_keys: usize,
         seq_idx: usize,
         value_operator: Arc<dyn MergeOperator>,
-    ) -> Self {
-        Self {
+    ) -> DfResult<Self> {
+        let input_schema = input.schema();
+        let output_indices = (0..input_schema.fields().len())
+            .filter(|idx| *idx != seq_idx)
+            .collect::<Vec<_>>();
+        let output_schema = Arc::new(input_schema.project(&output_indices)?);
+        let mapping = ProjectionMapping::from_indices(&output_indices, &input_schema)?;
+        let input_properties = input.properties();
+        let properties = PlanProperties::new(
+            input_properties
+                .equivalence_properties()
+                .project(&mapping, output_schema),
+            input_properties
+                .output_partitioning()
+                .project(&mapping, input_properties.equivalence_properties()),
+            input_properties.execution_mode(),
+        );
+
+        Ok(Self {
             input,
             num_primary_keys,
             seq_idx,
             value_operator,
-        }
+            properties,
+            metrics: ExecutionPlanMetricsSet::new(),
+        })
     }
 }
 impl DisplayAs for MergeExec {
@@ -147,7 +236,7 @@ impl ExecutionPlan for MergeExec {
     }
 
     fn properties(&self) -> &PlanProperties {
-        self.input.properties()
+        &self.properties
     }
 
     fn required_input_distribution(&self) -> Vec<Distribution> {
@@ -162,16 +251,25 @@ impl ExecutionPlan for MergeExec {
         vec![true; self.children().len()]
     }
 
+    fn statistics(&self) -> DfResult<Statistics> {
+        // Merge may reduce rows, so statistics of input are not exact any more.
+        let mut statistics = self.input.statistics()?.to_inexact();
+        if self.seq_idx < statistics.column_statistics.len() {
+            statistics.column_statistics.remove(self.seq_idx);
+        }
+        Ok(statistics)
+    }
+
     fn with_new_children(
         self: Arc<Self>,
         children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> DfResult<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(MergeExec::new(
+        Ok(Arc::new(MergeExec::try_new(
             Arc::clone(&children[0]),
             self.num_primary_keys,
             self.seq_idx,
             self.value_operator.clone(),
-        )))
+        )?))
     }
 
     fn execute(
@@ -188,8 +286,13 @@ impl ExecutionPlan for MergeExec {
             self.num_primary_keys,
             self.seq_idx,
             self.value_operator.clone(),
+            BaselineMetrics::new(&self.metrics, partition),
         )))
     }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
 }
 
 struct MergeStream {
@@ -200,6 +303,7 @@ struct MergeStream {
 
     pending_batch: Option<RecordBatch>,
     arrow_schema: SchemaRef,
+    baseline_metrics: BaselineMetrics,
 }
 
 impl MergeStream {
@@ -208,6 +312,7 @@ impl MergeStream {
         num_primary_keys: usize,
         seq_idx: usize,
         value_operator: MergeOperatorRef,
+        baseline_metrics: BaselineMetrics,
     ) -> Self {
         let fields = stream
             .schema()
@@ -232,6 +337,7 @@ impl MergeStream {
             value_operator,
             pending_batch: None,
             arrow_schema,
+            baseline_metrics,
         }
     }
 
@@ -267,6 +373,8 @@ impl MergeStream {
             return Ok(None);
         }
 
+        let elapsed_compute = self.baseline_metrics.elapsed_compute().clone();
+        let _timer = elapsed_compute.timer();
         // Group rows with the same primary keys
         let mut groupby_pk_batches = Vec::new();
         let mut start_idx = 0;
@@ -317,15 +425,11 @@ impl MergeStream {
         output_batches.remove_column(self.seq_idx);
         Ok(Some(output_batches))
     }
-}
 
-impl Stream for MergeStream {
-    type Item = DfResult<RecordBatch>;
-
-    fn poll_next(
-        mut self: Pin<&mut Self>,
+    fn poll_next_inner(
+        &mut self,
         ctx: &mut std::task::Context,
-    ) -> Poll<Option<Self::Item>> {
+    ) -> Poll<Option<DfResult<RecordBatch>>> {
         loop {
             match self.stream.poll_next_unpin(ctx) {
                 Poll::Pending => return Poll::Pending,
@@ -360,6 +464,18 @@ impl Stream for MergeStream {
     }
 }
 
+impl Stream for MergeStream {
+    type Item = DfResult<RecordBatch>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        ctx: &mut std::task::Context,
+    ) -> Poll<Option<Self::Item>> {
+        let poll = self.poll_next_inner(ctx);
+        self.baseline_metrics.record_poll(poll)
+    }
+}
+
 impl RecordBatchStream for MergeStream {
     fn schema(&self) -> SchemaRef {
         self.arrow_schema.clone()
@@ -370,6 +486,7 @@ pub struct ParquetReader {
     store: ObjectStoreRef,
     schema: StorageSchema,
     sst_path_gen: Arc<SstPathGenerator>,
+    config: ReadConfig,
 }
 
 impl ParquetReader {
@@ -377,11 +494,13 @@ impl ParquetReader {
         store: ObjectStoreRef,
         schema: StorageSchema,
         sst_path_gen: Arc<SstPathGenerator>,
+        config: ReadConfig,
     ) -> Self {
         Self {
             store,
             schema,
             sst_path_gen,
+            config,
         }
     }
 
@@ -402,6 +521,34 @@ impl ParquetReader {
         Ok(sort_exprs)
     }
 
+    /// Columns to read from SSTs in order to output `projections`.
+    ///
+    /// Primary keys and the seq column are always read since they are
+    /// required to dedup rows, columns referenced by predicates are read for
+    /// filtering, other value columns are skipped.
+    fn build_read_projections(
+        &self,
+        projections: &[usize],
+        predicates: &[Expr],
+    ) -> Result<Vec<usize>> {
+        let arrow_schema = &self.schema.arrow_schema;
+        let mut read_projections = (0..self.schema.num_primary_keys).collect::<Vec<_>>();
+        read_projections.extend(projections.iter().copied());
+        for expr in predicates {
+            for column in expr.column_refs() {
+                let idx = arrow_schema.index_of(&column.name).with_context(|| {
+                    format!("unknown column in predicate, name:{}", column.name)
+                })?;
+                read_projections.push(idx);
+            }
+        }
+        read_projections.push(self.schema.seq_idx);
+        read_projections.sort_unstable();
+        read_projections.dedup();
+
+        Ok(read_projections)
+    }
+
     pub fn build_df_plan(
         &self,
         ssts: Vec<SstFile>,
@@ -413,7 +560,29 @@ impl ParquetReader {
         let df_schema =
             DFSchema::try_from(self.schema.arrow_schema.clone()).context("build DFSchema")?;
         let sort_exprs = self.build_sort_exprs(&df_schema, true /* sort_seq */)?;
+        let read_projections = projections
+            .as_ref()
+            .map(|projections| self.build_read_projections(projections, &predicates))
+            .transpose()?;
+        let read_schema = match &read_projections {
+            Some(read_projections) => Arc::new(
+                self.schema
+                    .arrow_schema
+                    .project(read_projections)
+                    .context("project schema")?,
+            ),
+            None => self.schema.arrow_schema.clone(),
+        };
+        let read_df_schema =
+            DFSchema::try_from(read_schema.clone()).context("build read DFSchema")?;
 
+        // Rows with the same primary keys may be merged, so these are only
+        // upper bounds.
+        let statistics = Statistics {
+            num_rows: Precision::Inexact(ssts.iter().map(|f| f.meta().num_rows as usize).sum()),
+            total_byte_size: Precision::Inexact(ssts.iter().map(|f| f.size() as usize).sum()),
+            column_statistics: Statistics::unknown_column(&self.schema.arrow_schema),
+        };
         let file_groups = ssts
             .into_iter()
             .map(|f| {
@@ -424,20 +593,47 @@ impl ParquetReader {
             })
             .collect::<Vec<_>>();
         let scan_config = FileScanConfig::new(dummy_url, self.schema.arrow_schema.clone())
-            .with_output_ordering(vec![sort_exprs.clone(); file_groups.len()])
+            .with_output_ordering(vec![sort_exprs; file_groups.len()])
             .with_file_groups(file_groups)
-            .with_projection(projections);
+            .with_statistics(statistics)
+            .with_projection(read_projections.clone());
 
         let mut builder = ParquetExec::builder(scan_config).with_parquet_file_reader_factory(
-            Arc::new(DefaultParquetFileReaderFactory::new(self.store.clone())),
+            Arc::new(DefaultParquetFileReaderFactory::new(
+                self.store.clone(),
+                self.config.range_coalesce_gap,
+            )),
         );
-        let base_plan: Arc<dyn ExecutionPlan> = match conjunction(predicates) {
+        // Rows are deduped by primary keys after read, so only predicates on
+        // primary keys are evaluated before dedup, otherwise an older version
+        // would be output when the newer one is filtered out. Other predicates
+        // are evaluated after dedup. Rows are not deduped in keep-all mode, so
+        // all predicates are evaluated while reading.
+        let (pre_merge_predicates, post_merge_predicates): (Vec<_>, Vec<_>) =
+            match self.schema.update_mode {
+                UpdateMode::KeepAll => (predicates, Vec::new()),
+                UpdateMode::Overwrite | UpdateMode::Append => predicates
+                    .into_iter()
+                    .partition(|expr| self.is_primary_key_predicate(expr)),
+            };
+        let base_plan: Arc<dyn ExecutionPlan> = match conjunction(pre_merge_predicates) {
             Some(expr) => {
-                let filters = create_physical_expr(&expr, &df_schema, &ExecutionProps::new())
+                // Parquet predicate is evaluated against the file schema, while the filter
+                // is evaluated against the projected schema.
+                let parquet_filters =
+                    create_physical_expr(&expr, &df_schema, &ExecutionProps::new())
+                        .context("create physical expr")?;
+                let filters = create_physical_expr(&expr, &read_df_schema, &ExecutionProps::new())
                     .context("create physical expr")?;
 
-                builder = builder.with_predicate(filters.clone());
-                let parquet_exec = builder.build();
+                builder = builder.with_predicate(parquet_filters);
+                // When filters are pushed down, predicate is evaluated while decoding,
+                // so other columns of rows filtered out are never materialized.
+                // Filter exec is still kept since not all predicates can be pushed down.
+                let parquet_exec = builder
+                    .build()
+                    .with_pushdown_filters(self.config.enable_pushdown_filters)
+                    .with_reorder_filters(self.config.enable_pushdown_filters);
 
                 let filter_exec = FilterExec::try_new(filters, Arc::new(parquet_exec))
                     .context("create filter exec")?;
@@ -451,28 +647,160 @@ impl ParquetReader {
 
         // TODO: fetch using multiple threads since read from parquet will incur CPU
         // when convert between arrow and parquet.
-        let sort_exec =
-            SortPreservingMergeExec::new(sort_exprs, base_plan).with_round_robin_repartition(true);
+        let read_sort_exprs = self.build_sort_exprs(&read_df_schema, true /* sort_seq */)?;
+        let sort_exec = SortPreservingMergeExec::new(read_sort_exprs, base_plan)
+            .with_round_robin_repartition(true);
 
-        let merge_exec = MergeExec::new(
+        let seq_idx = read_schema
+            .index_of(SEQ_COLUMN_NAME)
+            .context("seq column not found")?;
+        let value_operator: Arc<dyn MergeOperator> = match self.schema.update_mode {
+            UpdateMode::Overwrite => Arc::new(LastValueOperator),
+            UpdateMode::Append => {
+                let value_idxes = match &read_projections {
+                    Some(read_projections) => self
+                        .schema
+                        .value_idxes
+                        .iter()
+                        .filter_map(|idx| read_projections.iter().position(|v| v == idx))
+                        .collect(),
+                    None => self.schema.value_idxes.clone(),
+                };
+                Arc::new(BytesMergeOperator::new(value_idxes))
+            }
+            // Rows are not merged, so seq column is removed by projection instead.
+            UpdateMode::KeepAll => {
+                return self.build_projection(
+                    Arc::new(sort_exec),
+                    projections,
+                    read_projections,
+                    true, // keep_seq
+                );
+            }
+        };
+        let merge_exec = MergeExec::try_new(
             Arc::new(sort_exec),
             self.schema.num_primary_keys,
-            self.schema.seq_idx,
-            match self.schema.update_mode {
-                UpdateMode::Overwrite => Arc::new(LastValueOperator),
-                UpdateMode::Append => {
-                    Arc::new(BytesMergeOperator::new(self.schema.value_idxes.clone()))
-                }
-            },
-        );
-        Ok(Arc::new(merge_exec))
+            seq_idx,
+            value_operator,
+        )
+        .context("create merge exec")?;
+        let filter_exec = self.build_filter(Arc::new(merge_exec), post_merge_predicates)?;
+
+        self.build_projection(
+            filter_exec,
+            projections,
+            read_projections,
+            false, // keep_seq
+        )
+    }
+
+    fn is_primary_key_predicate(&self, expr: &Expr) -> bool {
+        expr.column_refs().iter().all(|column| {
+            self.schema
+                .arrow_schema
+                .index_of(&column.name)
+                .is_ok_and(|idx| idx < self.schema.num_primary_keys)
+        })
+    }
+
+    /// Builds filter on top of `input` to evaluate `predicates` against its
+    /// output, returns `input` when there is no predicate.
+    fn build_filter(
+        &self,
+        input: Arc<dyn ExecutionPlan>,
+        predicates: Vec<Expr>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let Some(expr) = conjunction(predicates) else {
+            return Ok(input);
+        };
+        let df_schema = DFSchema::try_from(input.schema()).context("build DFSchema")?;
+        let filters = create_physical_expr(&expr, &df_schema, &ExecutionProps::new())
+            .context("create physical expr")?;
+        let filter_exec = FilterExec::try_new(filters, input).context("create filter exec")?;
+
+        Ok(Arc::new(filter_exec))
+    }
+
+    /// Schema of plans built by [`Self::build_df_plan`] with `projections`.
+    pub fn output_schema(&self, projections: Option<Vec<usize>>) -> Result<SchemaRef> {
+        let projections = self.output_projections(projections);
+        let schema = self
+            .schema
+            .arrow_schema
+            .project(&projections)
+            .context("project schema")?;
+        Ok(Arc::new(schema))
+    }
+
+    /// Columns output for `projections`, the seq column is never output.
+    fn output_projections(&self, projections: Option<Vec<usize>>) -> Vec<usize> {
+        projections
+            .unwrap_or_else(|| (0..self.schema.arrow_schema.fields.len()).collect())
+            .into_iter()
+            .filter(|idx| *idx != self.schema.seq_idx)
+            .collect()
+    }
+
+    /// Builds projection on top of `input` to output `projections`, which
+    /// removes columns only read for dedup or filter.
+    ///
+    /// `input` outputs columns in `read_projections`, with the seq column only
+    /// when `keep_seq` is true.
+    fn build_projection(
+        &self,
+        input: Arc<dyn ExecutionPlan>,
+        projections: Option<Vec<usize>>,
+        read_projections: Option<Vec<usize>>,
+        keep_seq: bool,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let all_columns = || (0..self.schema.arrow_schema.fields.len()).collect::<Vec<_>>();
+        let projections = self.output_projections(projections);
+        let input_projections = read_projections
+            .unwrap_or_else(all_columns)
+            .into_iter()
+            .filter(|idx| keep_seq || *idx != self.schema.seq_idx)
+            .collect::<Vec<_>>();
+        if projections == input_projections {
+            return Ok(input);
+        }
+
+        let exprs = projections
+            .iter()
+            .map(|idx| {
+                let name = self.schema.arrow_schema.field(*idx).name();
+                let input_idx = input_projections
+                    .iter()
+                    .position(|v| v == idx)
+                    .expect("projection must be read");
+                (
+                    Arc::new(Column::new(name, input_idx)) as Arc<dyn PhysicalExpr>,
+                    name.to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let projection_exec =
+            ProjectionExec::try_new(exprs, input).context("create projection exec")?;
+
+        Ok(Arc::new(projection_exec))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        fmt,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use async_trait::async_trait;
     use datafusion::logical_expr::{col, lit};
-    use object_store::local::LocalFileSystem;
+    use futures::stream::BoxStream;
+    use object_store::{
+        local::LocalFileSystem, memory::InMemory, GetOptions, GetResult, ListResult,
+        MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOpts, PutOptions, PutPayload,
+        PutResult,
+    };
     use test_log::test;
 
     use super::*;
@@ -515,6 +843,8 @@ mod tests {
     where
         I: IntoIterator<Item = RecordBatch>,
     {
+        let expected = expected.into_iter().collect_vec();
+        let expected_rows = expected.iter().map(|b| b.num_rows()).sum::<usize>();
         let stream = make_sendable_record_batches([
             record_batch!(
                 ("pk1", UInt8, vec![11, 11, 12, 12, 13]),
@@ -536,8 +866,10 @@ mod tests {
             .unwrap(),
         ]);
 
-        let stream = MergeStream::new(stream, 1, 2, merge_op);
+        let metrics = ExecutionPlanMetricsSet::new();
+        let stream = MergeStream::new(stream, 1, 2, merge_op, BaselineMetrics::new(&metrics, 0));
         check_stream(Box::pin(stream), expected).await;
+        assert_eq!(Some(expected_rows), metrics.clone_inner().output_rows());
     }
 
     #[tokio::test]
@@ -554,27 +886,25 @@ mod tests {
                 update_mode: UpdateMode::Overwrite,
             },
             Arc::new(SstPathGenerator::new("mock".to_string())),
+            ReadConfig::default(),
         );
 
+        let ssts = (100..103)
+            .map(|id| {
+                SstFile::new(
+                    id,
+                    FileMeta {
+                        max_sequence: id,
+                        num_rows: 1,
+                        size: 1,
+                        time_range: (1..10).into(),
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
         let expr = col("pk1").eq(lit(0_u8));
         let plan = reader
-            .build_df_plan(
-                (100..103)
-                    .map(|id| {
-                        SstFile::new(
-                            id,
-                            FileMeta {
-                                max_sequence: id,
-                                num_rows: 1,
-                                size: 1,
-                                time_range: (1..10).into(),
-                            },
-                        )
-                    })
-                    .collect(),
-                None,
-                vec![expr],
-            )
+            .build_df_plan(ssts.clone(), None, vec![expr])
             .unwrap();
         let display_plan =
             datafusion::physical_plan::display::DisplayableExecutionPlan::new(plan.as_ref())
@@ -587,5 +917,251 @@ mod tests {
 "#,
             format!("{display_plan}")
         );
+
+        let plan = reader.build_df_plan(ssts, None, vec![]).unwrap();
+        assert_eq!(Precision::Inexact(3), plan.statistics().unwrap().num_rows);
+    }
+
+    #[tokio::test]
+    async fn test_build_scan_plan_with_value_predicate() {
+        let schema = arrow_schema!(("pk1", UInt8), ("value", UInt8), (SEQ_COLUMN_NAME, UInt64));
+        let store = Arc::new(LocalFileSystem::new());
+        let reader = ParquetReader::new(
+            store,
+            StorageSchema {
+                arrow_schema: schema.clone(),
+                num_primary_keys: 1,
+                seq_idx: 2,
+                value_idxes: vec![1],
+                update_mode: UpdateMode::Overwrite,
+            },
+            Arc::new(SstPathGenerator::new("mock".to_string())),
+            ReadConfig::default(),
+        );
+
+        let ssts = vec![SstFile::new(
+            100,
+            FileMeta {
+                max_sequence: 100,
+                num_rows: 1,
+                size: 1,
+                time_range: (1..10).into(),
+            },
+        )];
+        // Predicate on value is evaluated after merge, so that older versions
+        // won't be output when newer one is filtered out.
+        let plan = reader
+            .build_df_plan(
+                ssts,
+                None,
+                vec![col("pk1").eq(lit(0_u8)), col("value").gt(lit(1_u8))],
+            )
+            .unwrap();
+        let display_plan =
+            datafusion::physical_plan::display::DisplayableExecutionPlan::new(plan.as_ref())
+                .indent(true);
+        assert_eq!(
+            r#"FilterExec: value@1 > 1
+  MergeExec: [primary_keys: 1, seq_idx: 2]
+    SortPreservingMergeExec: [pk1@0 ASC, __seq__@2 ASC]
+      FilterExec: pk1@0 = 0
+        ParquetExec: file_groups={1 group: [[mock/data/100.sst]]}, projection=[pk1, value, __seq__], output_ordering=[pk1@0 ASC, __seq__@2 ASC], predicate=pk1@0 = 0, pruning_predicate=CASE WHEN pk1_null_count@2 = pk1_row_count@3 THEN false ELSE pk1_min@0 <= 0 AND 0 <= pk1_max@1 END, required_guarantees=[pk1 in (0)]
+"#,
+            format!("{display_plan}")
+        );
+        assert_eq!(
+            vec!["pk1", "value"],
+            plan.schema()
+                .fields()
+                .iter()
+                .map(|f| f.name())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_scan_plan_with_projection() {
+        let schema = arrow_schema!(
+            ("pk1", UInt8),
+            ("v1", UInt8),
+            ("v2", UInt8),
+            ("v3", UInt8),
+            (SEQ_COLUMN_NAME, UInt64)
+        );
+        let store = Arc::new(LocalFileSystem::new());
+        let reader = ParquetReader::new(
+            store,
+            StorageSchema {
+                arrow_schema: schema.clone(),
+                num_primary_keys: 1,
+                seq_idx: 4,
+                value_idxes: vec![1, 2, 3],
+                update_mode: UpdateMode::Overwrite,
+            },
+            Arc::new(SstPathGenerator::new("mock".to_string())),
+            ReadConfig::default(),
+        );
+        let ssts = vec![SstFile::new(
+            100,
+            FileMeta {
+                max_sequence: 100,
+                num_rows: 1,
+                size: 1,
+                time_range: (1..10).into(),
+            },
+        )];
+
+        let plan = reader
+            .build_df_plan(ssts, Some(vec![1]), vec![col("v2").eq(lit(0_u8))])
+            .unwrap();
+        let display_plan =
+            datafusion::physical_plan::display::DisplayableExecutionPlan::new(plan.as_ref())
+                .indent(true)
+                .to_string();
+        // v3 is neither projected nor used by predicate, so it's not read.
+        assert!(
+            display_plan.contains("projection=[pk1, v1, v2, __seq__]"),
+            "{display_plan}"
+        );
+        let output_fields = plan
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["v1".to_string()], output_fields);
+    }
+
+    /// Object store which counts `get_range` and `get_ranges` requests.
+    #[derive(Debug)]
+    struct GetRangeCountingStore {
+        inner: InMemory,
+        num_get_range: AtomicUsize,
+        num_get_ranges: AtomicUsize,
+    }
+
+    impl GetRangeCountingStore {
+        fn new() -> Self {
+            Self {
+                inner: InMemory::new(),
+                num_get_range: AtomicUsize::new(0),
+                num_get_ranges: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl fmt::Display for GetRangeCountingStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "GetRangeCountingStore({})", self.inner)
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for GetRangeCountingStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> object_store::Result<PutResult> {
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOpts,
+        ) -> object_store::Result<Box<dyn MultipartUpload>> {
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> object_store::Result<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn get_range(
+            &self,
+            location: &Path,
+            range: Range<usize>,
+        ) -> object_store::Result<Bytes> {
+            self.num_get_range.fetch_add(1, Ordering::Relaxed);
+            self.inner.get_range(location, range).await
+        }
+
+        async fn get_ranges(
+            &self,
+            location: &Path,
+            ranges: &[Range<usize>],
+        ) -> object_store::Result<Vec<Bytes>> {
+            self.num_get_ranges.fetch_add(1, Ordering::Relaxed);
+            self.inner.get_ranges(location, ranges).await
+        }
+
+        async fn delete(&self, location: &Path) -> object_store::Result<()> {
+            self.inner.delete(location).await
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> object_store::Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_range_reader() {
+        let store = Arc::new(GetRangeCountingStore::new());
+        let location = Path::from("mock/data/100.sst");
+        store
+            .put(&location, PutPayload::from(vec![0_u8; 100]))
+            .await
+            .unwrap();
+        let object_meta = store.head(&location).await.unwrap();
+
+        let mut num_requests = Vec::new();
+        for range_coalesce_gap in [0, 64, OBJECT_STORE_COALESCE_DEFAULT] {
+            let factory = DefaultParquetFileReaderFactory::new(store.clone(), range_coalesce_gap);
+            let mut reader = factory
+                .create_reader(
+                    0,
+                    object_meta.clone().into(),
+                    None,
+                    &ExecutionPlanMetricsSet::new(),
+                )
+                .unwrap();
+            let before = (
+                store.num_get_range.load(Ordering::Relaxed),
+                store.num_get_ranges.load(Ordering::Relaxed),
+            );
+            let bytes = reader
+                .get_byte_ranges(vec![0..10, 20..30, 40..50])
+                .await
+                .unwrap();
+            assert_eq!(vec![10; 3], bytes.iter().map(|b| b.len()).collect_vec());
+            num_requests.push((
+                store.num_get_range.load(Ordering::Relaxed) - before.0,
+                store.num_get_ranges.load(Ordering::Relaxed) - before.1,
+            ));
+        }
+        // Ranges are 10 bytes apart, so they are only merged with the larger gap,
+        // and the default gap is left to the store's `get_ranges`.
+        assert_eq!(vec![(3, 0), (1, 0), (0, 1)], num_requests);
     }
 }
@@ -98,6 +98,14 @@ impl SstFile {
         self.inner.in_compaction.store(true, Ordering::Relaxed);
     }
 
+    /// Marks the file as in compaction, returns false if it's already marked.
+    pub fn try_mark_compaction(&self) -> bool {
+        self.inner
+            .in_compaction
+            .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
     pub fn unmark_compaction(&self) {
         self.inner.in_compaction.store(false, Ordering::Relaxed);
     }
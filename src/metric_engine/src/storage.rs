@@ -47,15 +47,16 @@ use parquet::{
     schema::types::ColumnPath,
 };
 use tokio::runtime::Runtime;
+use tracing::error;
 
 use crate::{
     compaction::CompactionScheduler,
-    config::{StorageConfig, WriteConfig},
+    config::{ParquetEncoding, StorageConfig, UpdateMode, WriteConfig},
     ensure,
-    manifest::{Manifest, ManifestRef},
+    manifest::{Manifest, ManifestRef, ManifestUpdate},
     read::ParquetReader,
     sst::{FileMeta, SstFile, SstPathGenerator},
-    types::{ObjectStoreRef, StorageSchema, TimeRange, WriteResult, SEQ_COLUMN_NAME},
+    types::{ObjectStoreRef, StorageSchema, TimeRange, Timestamp, WriteResult, SEQ_COLUMN_NAME},
     Result,
 };
 
@@ -71,6 +72,12 @@ pub struct ScanRequest {
     pub predicate: Vec<Expr>,
     /// `None` means all columns.
     pub projections: Option<Vec<usize>>,
+    /// Only scan a sample of SSTs, should be in (0, 1], `None` means no
+    /// sampling.
+    ///
+    /// Since sampling is done at SST level, rows may not be deduplicated
+    /// against SSTs skipped, so it's only suitable for exploratory queries.
+    pub sample_ratio: Option<f64>,
 }
 
 #[derive(Default)]
@@ -81,6 +88,9 @@ pub struct CompactRequest {}
 pub trait TimeMergeStorage {
     fn schema(&self) -> &SchemaRef;
 
+    /// Data is persisted as a new SST and recorded in the manifest before
+    /// this returns, so there is no WAL involved and a successful write is
+    /// already durable.
     async fn write(&self, req: WriteRequest) -> Result<()>;
 
     /// Implementation shoule ensure that the returned stream is sorted by time,
@@ -88,6 +98,27 @@ pub trait TimeMergeStorage {
     async fn scan(&self, req: ScanRequest) -> Result<SendableRecordBatchStream>;
 
     async fn compact(&self, req: CompactRequest) -> Result<()>;
+
+    /// Returns all live SSTs recorded in the manifest.
+    async fn list_ssts(&self) -> Result<Vec<SstFile>>;
+
+    /// Returns the time range covering all data, answered from manifest
+    /// without scanning, `None` when there is no data.
+    ///
+    /// Since it's built from time ranges of write requests, it may be wider
+    /// than the exact min/max timestamp.
+    async fn time_range(&self) -> Result<Option<TimeRange>>;
+
+    /// Removes SSTs whose data are all before `cutoff`, returns the number of
+    /// SSTs removed.
+    ///
+    /// `cutoff` is persisted, and scans are clipped to start from it, so SSTs
+    /// all before it are invisible even when they are not removed, e.g. in
+    /// compaction when purging. Rows carry no timestamp, so SSTs only
+    /// partially before `cutoff` are kept and read as a whole, rows before
+    /// `cutoff` in them remain readable until the whole SST is purged or
+    /// expires.
+    async fn purge_before(&self, cutoff: Timestamp) -> Result<usize>;
 }
 
 pub type TimeMergeStorageRef = Arc<(dyn TimeMergeStorage + Send + Sync)>;
@@ -124,11 +155,15 @@ pub struct CloudObjectStorage {
     write_props: WriterProperties,
     sst_path_gen: Arc<SstPathGenerator>,
     compact_scheduler: CompactionScheduler,
+    size_quota: Option<u64>,
+    max_scan_time_range: Option<Duration>,
+    max_scan_bytes: Option<u64>,
 }
 
 /// It will organize the data in the following way:
 /// ```plaintext
 /// {root_path}/manifest/snapshot
+/// {root_path}/manifest/purge_cutoff
 /// {root_path}/manifest/timestamp1
 /// {root_path}/manifest/timestamp2
 /// {root_path}/manifest/...
@@ -163,6 +198,17 @@ impl CloudObjectStorage {
                 arrow_schema.metadata.clone(),
             ));
             let update_mode = storage_opts.update_mode;
+            if let UpdateMode::Append = update_mode {
+                for idx in &value_idxes {
+                    let field = arrow_schema.field(*idx);
+                    ensure!(
+                        field.data_type() == &DataType::Binary,
+                        "value column must be binary in append mode, name:{}, type:{}",
+                        field.name(),
+                        field.data_type()
+                    );
+                }
+            }
             StorageSchema {
                 arrow_schema,
                 num_primary_keys,
@@ -171,6 +217,15 @@ impl CloudObjectStorage {
                 update_mode,
             }
         };
+        ensure!(
+            storage_opts.write.max_row_group_size > 0,
+            "max_row_group_size must be greater than 0"
+        );
+        ensure!(
+            storage_opts.write.data_page_size_limit > 0,
+            "data_page_size_limit must be greater than 0"
+        );
+        Self::check_encodings(&storage_opts.write, &schema.arrow_schema)?;
         let manifest = Manifest::try_new(
             path.clone(),
             store.clone(),
@@ -181,10 +236,13 @@ impl CloudObjectStorage {
         let manifest = Arc::new(manifest);
         let write_props = Self::build_write_props(storage_opts.write, num_primary_keys);
         let sst_path_gen = Arc::new(SstPathGenerator::new(path.clone()));
+        let max_scan_time_range = storage_opts.read.max_scan_time_range;
+        let max_scan_bytes = storage_opts.read.max_scan_bytes;
         let parquet_reader = Arc::new(ParquetReader::new(
             store.clone(),
             schema.clone(),
             sst_path_gen.clone(),
+            storage_opts.read,
         ));
         let compact_scheduler = CompactionScheduler::new(
             runtimes.sst_compact_runtime.clone(),
@@ -208,6 +266,9 @@ impl CloudObjectStorage {
             write_props,
             sst_path_gen,
             compact_scheduler,
+            size_quota: storage_opts.size_quota,
+            max_scan_time_range,
+            max_scan_bytes,
         })
     }
 
@@ -285,6 +346,43 @@ impl CloudObjectStorage {
         Ok(res)
     }
 
+    /// Parquet only supports byte stream split for fixed width columns, so
+    /// reject other columns here instead of failing every write.
+    fn check_encodings(write_options: &WriteConfig, arrow_schema: &Schema) -> Result<()> {
+        for field in arrow_schema.fields() {
+            let encoding = write_options
+                .column_options
+                .as_ref()
+                .and_then(|opts| opts.get(field.name()))
+                .and_then(|opt| opt.encoding.as_ref())
+                .unwrap_or(&write_options.encoding);
+            if let ParquetEncoding::ByteStreamSplit = encoding {
+                let data_type = field.data_type();
+                ensure!(
+                    data_type.is_integer()
+                        || data_type.is_floating()
+                        || matches!(
+                            data_type,
+                            DataType::Decimal128(_, _)
+                                | DataType::Decimal256(_, _)
+                                | DataType::FixedSizeBinary(_)
+                                | DataType::Date32
+                                | DataType::Date64
+                                | DataType::Time32(_)
+                                | DataType::Time64(_)
+                                | DataType::Timestamp(_, _)
+                                | DataType::Duration(_)
+                        ),
+                    "byte stream split encoding is not supported for column, name:{}, type:{}",
+                    field.name(),
+                    data_type
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     fn build_write_props(write_options: WriteConfig, num_primary_key: usize) -> WriterProperties {
         let sorting_columns = write_options.enable_sorting_columns.then(|| {
             (0..num_primary_key)
@@ -296,12 +394,14 @@ impl CloudObjectStorage {
 
         let mut builder = WriterProperties::builder()
             .set_max_row_group_size(write_options.max_row_group_size)
+            .set_data_page_size_limit(write_options.data_page_size_limit)
             .set_write_batch_size(write_options.write_bacth_size)
             .set_sorting_columns(sorting_columns)
             .set_dictionary_enabled(write_options.enable_dict)
             .set_bloom_filter_enabled(write_options.enable_bloom_filter)
             .set_encoding(write_options.encoding.into())
-            .set_compression(write_options.compression.into());
+            .set_compression(write_options.compression.into())
+            .set_statistics_enabled(write_options.statistics.into());
 
         if write_options.column_options.is_none() {
             return builder.build();
@@ -320,7 +420,10 @@ impl CloudObjectStorage {
                 builder = builder.set_column_encoding(col_path.clone(), encoding.into());
             }
             if let Some(compression) = col_opt.compression {
-                builder = builder.set_column_compression(col_path, compression.into());
+                builder = builder.set_column_compression(col_path.clone(), compression.into());
+            }
+            if let Some(statistics) = col_opt.statistics {
+                builder = builder.set_column_statistics_enabled(col_path, statistics.into());
             }
         }
 
@@ -345,6 +448,14 @@ impl TimeMergeStorage for CloudObjectStorage {
             );
         }
 
+        if let Some(quota) = self.size_quota {
+            let used = self.manifest.total_size();
+            ensure!(
+                used < quota,
+                "storage size quota exceeded, used:{used}, quota:{quota}"
+            );
+        }
+
         let num_rows = req.batch.num_rows();
         let WriteResult {
             id: file_id,
@@ -363,11 +474,49 @@ impl TimeMergeStorage for CloudObjectStorage {
     }
 
     async fn scan(&self, req: ScanRequest) -> Result<SendableRecordBatchStream> {
-        let total_ssts = self.manifest.find_ssts(&req.range).await;
+        if let Some(limit) = self.max_scan_time_range {
+            let span = req.range.end.0.saturating_sub(req.range.start.0);
+            ensure!(
+                span <= limit.as_millis() as i64,
+                "scan time range exceeds limit, range:{:?}, limit:{limit:?}",
+                req.range
+            );
+        }
+        // Data before purge cutoff is invisible, even if some SSTs all before it
+        // are not removed.
+        let mut total_ssts = match self.manifest.purge_cutoff().await {
+            Some(cutoff) if cutoff >= req.range.end => Vec::new(),
+            Some(cutoff) if cutoff > req.range.start => {
+                let range = TimeRange::new(cutoff, req.range.end);
+                self.manifest.find_ssts(&range).await
+            }
+            _ => self.manifest.find_ssts(&req.range).await,
+        };
+        if let Some(ratio) = req.sample_ratio {
+            ensure!(
+                ratio > 0.0 && ratio <= 1.0,
+                "sample ratio should be in (0, 1], value:{ratio}"
+            );
+            // Pick the SST whenever the accumulated ratio rounded up increases,
+            // so the number of SSTs picked follows the ratio, and at least one
+            // SST is picked when any matches.
+            total_ssts = total_ssts
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| ((i + 1) as f64 * ratio).ceil() > (*i as f64 * ratio).ceil())
+                .map(|(_, f)| f)
+                .collect();
+        }
+        if let Some(limit) = self.max_scan_bytes {
+            let scan_bytes = total_ssts.iter().map(|f| f.size() as u64).sum::<u64>();
+            ensure!(
+                scan_bytes <= limit,
+                "scan bytes exceeds limit, bytes:{scan_bytes}, limit:{limit}"
+            );
+        }
         if total_ssts.is_empty() {
-            return Ok(Box::pin(EmptyRecordBatchStream::new(
-                self.schema.arrow_schema.clone(),
-            )));
+            let schema = self.parquet_reader.output_schema(req.projections)?;
+            return Ok(Box::pin(EmptyRecordBatchStream::new(schema)));
         }
 
         let ssts_by_segment = total_ssts.into_iter().group_by(|file| {
@@ -400,16 +549,78 @@ impl TimeMergeStorage for CloudObjectStorage {
     async fn compact(&self, _req: CompactRequest) -> Result<()> {
         self.compact_scheduler.trigger_compaction()
     }
+
+    async fn list_ssts(&self) -> Result<Vec<SstFile>> {
+        Ok(self.manifest.all_ssts().await)
+    }
+
+    async fn time_range(&self) -> Result<Option<TimeRange>> {
+        Ok(self.manifest.time_range().await)
+    }
+
+    async fn purge_before(&self, cutoff: Timestamp) -> Result<usize> {
+        // Persist cutoff first, so purged data stay invisible even if files
+        // below are not removed.
+        self.manifest.set_purge_cutoff(cutoff).await?;
+
+        // Claim files atomically so compaction won't pick them, files already
+        // in compaction are skipped.
+        let to_purges = self
+            .manifest
+            .all_ssts()
+            .await
+            .into_iter()
+            .filter(|f| f.meta().time_range.end <= cutoff && f.try_mark_compaction())
+            .collect_vec();
+        if to_purges.is_empty() {
+            return Ok(0);
+        }
+
+        let to_deletes = to_purges.iter().map(|f| f.id()).collect_vec();
+        if let Err(e) = self
+            .manifest
+            .update(ManifestUpdate::new(Vec::new(), to_deletes.clone()))
+            .await
+        {
+            for f in &to_purges {
+                f.unmark_compaction();
+            }
+            return Err(e);
+        }
+
+        // Manifest is updated, failing to delete files only leaves garbage.
+        for id in to_deletes {
+            let path = Path::from(self.sst_path_gen.generate(id));
+            if let Err(e) = self.store.delete(&path).await {
+                error!("Failed to delete purged sst, path:{path}, err:{e}");
+            }
+        }
+
+        Ok(to_purges.len())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use datafusion::logical_expr::{col, lit};
+    use std::collections::{BTreeMap, HashMap};
+
+    use arrow::compute::concat_batches;
+    use datafusion::{
+        common::ScalarValue,
+        logical_expr::{col, lit},
+        physical_plan::common::collect,
+    };
     use object_store::local::LocalFileSystem;
+    use parquet::file::properties::EnabledStatistics;
     use test_log::test;
 
     use super::*;
-    use crate::{arrow_schema, record_batch, test_util::check_stream, types::Timestamp};
+    use crate::{
+        arrow_schema,
+        config::{ColumnOptions, ParquetEncoding, ParquetStatistics, ReadConfig},
+        record_batch,
+        test_util::check_stream,
+    };
 
     fn build_runtimes() -> StorageRuntimes {
         let rt = Arc::new(Runtime::new().unwrap());
@@ -465,11 +676,20 @@ mod tests {
                 .await
                 .unwrap();
 
+            let ssts = storage.list_ssts().await.unwrap();
+            assert_eq!(2, ssts.len());
+            assert_eq!(9, ssts.iter().map(|f| f.meta().num_rows).sum::<u32>());
+            assert_eq!(
+                Some(TimeRange::from(1..20)),
+                storage.time_range().await.unwrap()
+            );
+
             let result_stream = storage
                 .scan(ScanRequest {
                     range: TimeRange::new(Timestamp(0), Timestamp::MAX),
                     predicate: vec![],
                     projections: None,
+                    sample_ratio: None,
                 })
                 .await
                 .unwrap();
@@ -497,6 +717,7 @@ mod tests {
                     range: TimeRange::new(Timestamp(0), Timestamp::MAX),
                     predicate: vec![expr],
                     projections: None,
+                    sample_ratio: None,
                 })
                 .await
                 .unwrap();
@@ -515,6 +736,1198 @@ mod tests {
                 .unwrap(),
             ];
             check_stream(result_stream, expected_batch).await;
+
+            // test with projection
+            let expr = col("pk1").eq(lit(11_u8));
+            let result_stream = storage
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                    predicate: vec![expr],
+                    projections: Some(vec![2]),
+                    sample_ratio: None,
+                })
+                .await
+                .unwrap();
+            let expected_batch = [
+                record_batch!(("value", Int64, vec![77])).unwrap(),
+                record_batch!(("value", Int64, vec![22])).unwrap(),
+            ];
+            check_stream(result_stream, expected_batch).await;
+        });
+    }
+
+    #[test(test)]
+    fn test_storage_scan_with_projection() {
+        let schema = arrow_schema!(("pk1", UInt8), ("v1", Int64), ("v2", Int64), ("v3", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let batches = vec![
+                record_batch!(
+                    ("pk1", UInt8, vec![1, 2]),
+                    ("v1", Int64, vec![10, 20]),
+                    ("v2", Int64, vec![100, 200]),
+                    ("v3", Int64, vec![1000, 2000])
+                )
+                .unwrap(),
+                record_batch!(
+                    ("pk1", UInt8, vec![1]),
+                    ("v1", Int64, vec![11]),
+                    ("v2", Int64, vec![101]),
+                    ("v3", Int64, vec![1001])
+                )
+                .unwrap(),
+            ];
+            let storage = build_storage_with_data(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig::default(),
+                batches
+                    .into_iter()
+                    .zip([TimeRange::from(0..10), TimeRange::from(10..20)]),
+            )
+            .await;
+
+            // v2 is only read for filter, v3 is not read at all.
+            let result_stream = storage
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                    predicate: vec![col("v2").gt_eq(lit(101_i64))],
+                    projections: Some(vec![1]),
+                    sample_ratio: None,
+                })
+                .await
+                .unwrap();
+            let expected_batch = [
+                record_batch!(("v1", Int64, vec![11])).unwrap(),
+                record_batch!(("v1", Int64, vec![20])).unwrap(),
+            ];
+            check_stream(result_stream, expected_batch).await;
+        });
+    }
+
+    #[test(test)]
+    fn test_storage_scan_empty_range_with_projection() {
+        let schema = arrow_schema!(("pk1", UInt8), ("v1", Int64), ("v2", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let batch = record_batch!(
+                ("pk1", UInt8, vec![1]),
+                ("v1", Int64, vec![10]),
+                ("v2", Int64, vec![100])
+            )
+            .unwrap();
+            let storage = build_storage_with_data(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig::default(),
+                [(batch, TimeRange::from(0..10))],
+            )
+            .await;
+
+            // No SST matches, the schema should be the same as non-empty scans.
+            for (projections, expected) in
+                [(Some(vec![2]), vec!["v2"]), (None, vec!["pk1", "v1", "v2"])]
+            {
+                let result_stream = storage
+                    .scan(ScanRequest {
+                        range: TimeRange::new(Timestamp(100), Timestamp(200)),
+                        predicate: vec![],
+                        projections,
+                        sample_ratio: None,
+                    })
+                    .await
+                    .unwrap();
+                let fields = result_stream
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|f| f.name().clone())
+                    .collect::<Vec<_>>();
+                assert_eq!(fields, expected);
+                check_stream(result_stream, Vec::<RecordBatch>::new()).await;
+            }
+        });
+    }
+
+    #[test(test)]
+    fn test_storage_append_mode_scan_with_projection() {
+        let schema = arrow_schema!(("pk1", UInt8), ("v1", Binary), ("v2", Binary));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let batches = vec![
+                record_batch!(
+                    ("pk1", UInt8, vec![1]),
+                    ("v1", Binary, vec![b"a"]),
+                    ("v2", Binary, vec![b"x"])
+                )
+                .unwrap(),
+                record_batch!(
+                    ("pk1", UInt8, vec![1]),
+                    ("v1", Binary, vec![b"b"]),
+                    ("v2", Binary, vec![b"y"])
+                )
+                .unwrap(),
+            ];
+            let storage = build_storage_with_data(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig {
+                    update_mode: UpdateMode::Append,
+                    ..Default::default()
+                },
+                batches
+                    .into_iter()
+                    .zip([TimeRange::from(0..10), TimeRange::from(10..20)]),
+            )
+            .await;
+
+            // v1 is not read, so v2 is the only value column to merge.
+            let result_stream = storage
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                    predicate: vec![],
+                    projections: Some(vec![0, 2]),
+                    sample_ratio: None,
+                })
+                .await
+                .unwrap();
+            let expected_batch =
+                [record_batch!(("pk1", UInt8, vec![1]), ("v2", Binary, vec![b"xy"])).unwrap()];
+            check_stream(result_stream, expected_batch).await;
+        });
+    }
+
+    async fn build_storage(
+        root_dir: String,
+        runtimes: StorageRuntimes,
+        schema: SchemaRef,
+        num_primary_keys: usize,
+        config: StorageConfig,
+    ) -> Result<CloudObjectStorage> {
+        CloudObjectStorage::try_new(
+            root_dir,
+            Duration::from_hours(2),
+            Arc::new(LocalFileSystem::new()),
+            schema,
+            num_primary_keys,
+            config,
+            runtimes,
+        )
+        .await
+    }
+
+    /// Creates storage with `config`, and writes each batch as one SST.
+    async fn build_storage_with_data(
+        root_dir: String,
+        runtimes: StorageRuntimes,
+        schema: SchemaRef,
+        num_primary_keys: usize,
+        config: StorageConfig,
+        batches: impl IntoIterator<Item = (RecordBatch, TimeRange)>,
+    ) -> CloudObjectStorage {
+        let storage = build_storage(root_dir, runtimes, schema, num_primary_keys, config)
+            .await
+            .unwrap();
+        for (batch, time_range) in batches {
+            storage
+                .write(WriteRequest {
+                    batch,
+                    time_range,
+                    enable_check: true,
+                })
+                .await
+                .unwrap();
+        }
+
+        storage
+    }
+
+    /// Batches of `("pk1", UInt8), ("pk2", UInt8), ("value", Int64)`, same as
+    /// the ones written in `test_storage_write_and_scan`.
+    fn default_batches() -> Vec<(RecordBatch, TimeRange)> {
+        let batches = [
+            record_batch!(
+                ("pk1", UInt8, vec![11, 11, 9, 10, 5]),
+                ("pk2", UInt8, vec![100, 100, 1, 2, 3]),
+                ("value", Int64, vec![2, 7, 4, 6, 1])
+            )
+            .unwrap(),
+            record_batch!(
+                ("pk1", UInt8, vec![11, 11, 9, 10]),
+                ("pk2", UInt8, vec![100, 99, 1, 2]),
+                ("value", Int64, vec![22, 77, 44, 66])
+            )
+            .unwrap(),
+        ];
+        batches
+            .into_iter()
+            .zip([TimeRange::from(1..10), TimeRange::from(10..20)])
+            .collect()
+    }
+
+    #[test(test)]
+    fn test_storage_scan_with_pushdown_filters() {
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let storage = build_storage_with_data(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                arrow_schema!(("pk1", UInt8), ("pk2", UInt8), ("value", Int64)),
+                2, // num_primary_keys
+                StorageConfig {
+                    read: ReadConfig {
+                        enable_pushdown_filters: true,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                default_batches(),
+            )
+            .await;
+
+            let result_stream = storage
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                    predicate: vec![col("pk1").eq(lit(11_u8))],
+                    projections: None,
+                    sample_ratio: None,
+                })
+                .await
+                .unwrap();
+            let expected_batch = [
+                record_batch!(
+                    ("pk1", UInt8, vec![11]),
+                    ("pk2", UInt8, vec![99]),
+                    ("value", Int64, vec![77])
+                )
+                .unwrap(),
+                record_batch!(
+                    ("pk1", UInt8, vec![11]),
+                    ("pk2", UInt8, vec![100]),
+                    ("value", Int64, vec![22])
+                )
+                .unwrap(),
+            ];
+            check_stream(result_stream, expected_batch).await;
+        });
+    }
+
+    #[test(test)]
+    fn test_storage_scan_with_pushdown_filters_and_projection() {
+        let mut results = Vec::new();
+        for enable_pushdown_filters in [false, true] {
+            let schema = arrow_schema!(("pk1", UInt8), ("v1", Int64), ("v2", Int64), ("v3", Int64));
+            let root_dir = temp_dir::TempDir::new().unwrap();
+            let runtimes = build_runtimes();
+            let result = runtimes.sst_compact_runtime.clone().block_on(async move {
+                let batches = vec![
+                    record_batch!(
+                        ("pk1", UInt8, vec![1, 2, 3]),
+                        ("v1", Int64, vec![10, 20, 30]),
+                        ("v2", Int64, vec![100, 200, 300]),
+                        ("v3", Int64, vec![1000, 2000, 3000])
+                    )
+                    .unwrap(),
+                    record_batch!(
+                        ("pk1", UInt8, vec![1, 3]),
+                        ("v1", Int64, vec![11, 31]),
+                        ("v2", Int64, vec![101, 301]),
+                        ("v3", Int64, vec![1001, 3001])
+                    )
+                    .unwrap(),
+                ];
+                let storage = build_storage_with_data(
+                    root_dir.path().to_string_lossy().to_string(),
+                    runtimes,
+                    schema,
+                    1, // num_primary_keys
+                    StorageConfig {
+                        read: ReadConfig {
+                            enable_pushdown_filters,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    batches
+                        .into_iter()
+                        .zip([TimeRange::from(0..10), TimeRange::from(10..20)]),
+                )
+                .await;
+
+                // v2 is only read for filter, so predicate and projection are
+                // evaluated against different schemas. For pk1=3, the older
+                // version passes the second predicate but the newer one doesn't,
+                // so no version should be returned.
+                let mut results = Vec::new();
+                for predicate in [col("v2").gt_eq(lit(101_i64)), col("v2").lt_eq(lit(300_i64))] {
+                    let result_stream = storage
+                        .scan(ScanRequest {
+                            range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                            predicate: vec![predicate],
+                            projections: Some(vec![1, 3]),
+                            sample_ratio: None,
+                        })
+                        .await
+                        .unwrap();
+                    let result_schema = result_stream.schema();
+                    let result = collect(result_stream).await.unwrap();
+                    results.push(concat_batches(&result_schema, &result).unwrap());
+                }
+                results
+            });
+            results.push(result);
+        }
+
+        let expected = vec![
+            record_batch!(
+                ("v1", Int64, vec![11, 20, 31]),
+                ("v3", Int64, vec![1001, 2000, 3001])
+            )
+            .unwrap(),
+            record_batch!(("v1", Int64, vec![11, 20]), ("v3", Int64, vec![1001, 2000])).unwrap(),
+        ];
+        assert_eq!(expected, results[0]);
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test(test)]
+    fn test_storage_scan_with_range_coalesce_gap() {
+        for range_coalesce_gap in [0, 64 * 1024 * 1024] {
+            let root_dir = temp_dir::TempDir::new().unwrap();
+            let runtimes = build_runtimes();
+            runtimes.sst_compact_runtime.clone().block_on(async move {
+                let storage = build_storage_with_data(
+                    root_dir.path().to_string_lossy().to_string(),
+                    runtimes,
+                    arrow_schema!(("pk1", UInt8), ("pk2", UInt8), ("value", Int64)),
+                    2, // num_primary_keys
+                    StorageConfig {
+                        read: ReadConfig {
+                            range_coalesce_gap,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    default_batches(),
+                )
+                .await;
+
+                let result_stream = storage
+                    .scan(ScanRequest {
+                        range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                        predicate: vec![],
+                        projections: None,
+                        sample_ratio: None,
+                    })
+                    .await
+                    .unwrap();
+                let expected_batch = [
+                    record_batch!(
+                        ("pk1", UInt8, vec![5, 9, 10, 11]),
+                        ("pk2", UInt8, vec![3, 1, 2, 99]),
+                        ("value", Int64, vec![1, 44, 66, 77])
+                    )
+                    .unwrap(),
+                    record_batch!(
+                        ("pk1", UInt8, vec![11]),
+                        ("pk2", UInt8, vec![100]),
+                        ("value", Int64, vec![22])
+                    )
+                    .unwrap(),
+                ];
+                check_stream(result_stream, expected_batch).await;
+            });
+        }
+    }
+
+    /// Deterministic pseudo random generator, so failures are reproducible.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0 >> 33
+        }
+    }
+
+    /// Triggers compaction and waits until it's finished.
+    async fn compact_and_wait(storage: &CloudObjectStorage) {
+        let num_ssts = storage.list_ssts().await.unwrap().len();
+        storage.compact(CompactRequest::default()).await.unwrap();
+        for _ in 0..100 {
+            if storage.list_ssts().await.unwrap().len() < num_ssts {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("compaction not finished, num_ssts:{num_ssts}");
+    }
+
+    /// Rows expected to be scanned after `writes`, which are the values of
+    /// each write keyed by primary keys. Rows are ordered by primary keys, and
+    /// versions of the same primary keys are ordered by write.
+    fn reference_rows(
+        update_mode: &UpdateMode,
+        writes: &[BTreeMap<(u8, u8), [ScalarValue; 2]>],
+    ) -> Vec<Vec<ScalarValue>> {
+        let mut versions_by_pk: BTreeMap<(u8, u8), Vec<&[ScalarValue; 2]>> = BTreeMap::new();
+        for rows in writes {
+            for (pk, values) in rows {
+                versions_by_pk.entry(*pk).or_default().push(values);
+            }
+        }
+
+        let mut rows = Vec::new();
+        for ((pk1, pk2), versions) in versions_by_pk {
+            let values = match update_mode {
+                UpdateMode::Overwrite => vec![versions.last().unwrap().to_vec()],
+                UpdateMode::KeepAll => versions.iter().map(|v| v.to_vec()).collect(),
+                UpdateMode::Append => {
+                    let concat = |i: usize| {
+                        let bytes = versions
+                            .iter()
+                            .flat_map(|v| match &v[i] {
+                                ScalarValue::Binary(Some(bytes)) => bytes.clone(),
+                                v => panic!("unexpected value in append mode, value:{v}"),
+                            })
+                            .collect();
+                        ScalarValue::Binary(Some(bytes))
+                    };
+                    vec![vec![concat(0), concat(1)]]
+                }
+            };
+            for values in values {
+                let mut row = vec![ScalarValue::UInt8(Some(pk1)), ScalarValue::UInt8(Some(pk2))];
+                row.extend(values);
+                rows.push(row);
+            }
+        }
+
+        rows
+    }
+
+    /// Writes random batches and compacts them, then checks scans with random
+    /// predicates and projections against a naive in-memory reference.
+    async fn check_scan_against_reference(
+        storage: CloudObjectStorage,
+        schema: SchemaRef,
+        update_mode: UpdateMode,
+        rng: &mut Lcg,
+    ) {
+        // Values are binary in append mode, since only binary values can be
+        // appended.
+        let is_binary = matches!(update_mode, UpdateMode::Append);
+        let random_value = |rng: &mut Lcg| {
+            if is_binary {
+                ScalarValue::Binary(Some(vec![b'a' + (rng.next() % 26) as u8]))
+            } else {
+                ScalarValue::Int64(Some((rng.next() % 100) as i64))
+            }
+        };
+
+        let mut writes = Vec::new();
+        for round in 0..10 {
+            // Primary keys within one batch are unique, since rows in the same SST
+            // share the same sequence.
+            let mut rows = BTreeMap::new();
+            for _ in 0..20 {
+                let pk = ((rng.next() % 8) as u8, (rng.next() % 8) as u8);
+                rows.insert(pk, [random_value(rng), random_value(rng)]);
+            }
+            // Write in reverse order to make sure rows are sorted by storage.
+            let columns = vec![
+                ScalarValue::iter_to_array(rows.keys().rev().map(|pk| ScalarValue::from(pk.0)))
+                    .unwrap(),
+                ScalarValue::iter_to_array(rows.keys().rev().map(|pk| ScalarValue::from(pk.1)))
+                    .unwrap(),
+                ScalarValue::iter_to_array(rows.values().rev().map(|v| v[0].clone())).unwrap(),
+                ScalarValue::iter_to_array(rows.values().rev().map(|v| v[1].clone())).unwrap(),
+            ];
+            let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+            storage
+                .write(WriteRequest {
+                    batch,
+                    time_range: (round..round + 1).into(),
+                    enable_check: true,
+                })
+                .await
+                .unwrap();
+            writes.push(rows);
+
+            // So that scans read both compacted and newly written SSTs.
+            if round == 4 {
+                compact_and_wait(&storage).await;
+            }
+        }
+
+        let reference = reference_rows(&update_mode, &writes);
+        for _ in 0..30 {
+            // Predicates on values are only generated when they can be compared
+            // with numbers.
+            let num_columns = if is_binary { 2 } else { 4 };
+            let mut predicates = Vec::new();
+            for _ in 0..rng.next() % 3 {
+                let idx = (rng.next() % num_columns) as usize;
+                let threshold = if idx < 2 {
+                    ScalarValue::UInt8(Some((rng.next() % 8) as u8))
+                } else {
+                    ScalarValue::Int64(Some((rng.next() % 100) as i64))
+                };
+                let greater_eq = rng.next() % 2 == 0;
+                predicates.push((idx, threshold, greater_eq));
+            }
+            let projections = if rng.next() % 4 == 0 {
+                None
+            } else {
+                let mut projections = (0..4).filter(|_| rng.next() % 2 == 0).collect::<Vec<_>>();
+                if projections.is_empty() {
+                    projections.push((rng.next() % 4) as usize);
+                }
+                Some(projections)
+            };
+
+            let expected = reference
+                .iter()
+                .filter(|row| {
+                    predicates.iter().all(|(idx, threshold, greater_eq)| {
+                        let ord = row[*idx].partial_cmp(threshold).unwrap();
+                        if *greater_eq {
+                            ord.is_ge()
+                        } else {
+                            ord.is_lt()
+                        }
+                    })
+                })
+                .map(|row| match &projections {
+                    Some(projections) => projections.iter().map(|idx| row[*idx].clone()).collect(),
+                    None => row.clone(),
+                })
+                .collect::<Vec<_>>();
+
+            let predicate = predicates
+                .iter()
+                .map(|(idx, threshold, greater_eq)| {
+                    let column = col(schema.field(*idx).name());
+                    if *greater_eq {
+                        column.gt_eq(lit(threshold.clone()))
+                    } else {
+                        column.lt(lit(threshold.clone()))
+                    }
+                })
+                .collect::<Vec<_>>();
+            let batches = collect(
+                storage
+                    .scan(ScanRequest {
+                        range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                        predicate: predicate.clone(),
+                        projections: projections.clone(),
+                        sample_ratio: None,
+                    })
+                    .await
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+            let mut actual = Vec::new();
+            for batch in batches {
+                for i in 0..batch.num_rows() {
+                    let row = batch
+                        .columns()
+                        .iter()
+                        .map(|column| ScalarValue::try_from_array(column, i).unwrap())
+                        .collect::<Vec<_>>();
+                    actual.push(row);
+                }
+            }
+
+            assert_eq!(
+                expected, actual,
+                "update_mode:{update_mode:?}, predicate:{predicate:?}, projections:{projections:?}"
+            );
+        }
+    }
+
+    #[test(test)]
+    fn test_storage_scan_against_reference() {
+        let mut rng = Lcg(42);
+        for update_mode in [
+            UpdateMode::Overwrite,
+            UpdateMode::Append,
+            UpdateMode::KeepAll,
+        ] {
+            let schema = match update_mode {
+                UpdateMode::Append => {
+                    arrow_schema!(
+                        ("pk1", UInt8),
+                        ("pk2", UInt8),
+                        ("v1", Binary),
+                        ("v2", Binary)
+                    )
+                }
+                UpdateMode::Overwrite | UpdateMode::KeepAll => {
+                    arrow_schema!(("pk1", UInt8), ("pk2", UInt8), ("v1", Int64), ("v2", Int64))
+                }
+            };
+            let root_dir = temp_dir::TempDir::new().unwrap();
+            let runtimes = build_runtimes();
+            let rng = &mut rng;
+            runtimes.sst_compact_runtime.clone().block_on(async move {
+                let storage = build_storage(
+                    root_dir.path().to_string_lossy().to_string(),
+                    runtimes,
+                    schema.clone(),
+                    2, // num_primary_keys
+                    StorageConfig {
+                        update_mode: update_mode.clone(),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+                check_scan_against_reference(storage, schema, update_mode, rng).await;
+            });
+        }
+    }
+
+    #[test(test)]
+    fn test_storage_keep_all_mode_scan() {
+        let schema = arrow_schema!(("pk1", UInt8), ("v1", Int64), ("v2", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let batches = vec![
+                record_batch!(
+                    ("pk1", UInt8, vec![1, 2]),
+                    ("v1", Int64, vec![10, 20]),
+                    ("v2", Int64, vec![100, 200])
+                )
+                .unwrap(),
+                record_batch!(
+                    ("pk1", UInt8, vec![1, 3]),
+                    ("v1", Int64, vec![11, 30]),
+                    ("v2", Int64, vec![101, 300])
+                )
+                .unwrap(),
+            ];
+            let storage = build_storage_with_data(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig {
+                    update_mode: UpdateMode::KeepAll,
+                    ..Default::default()
+                },
+                batches
+                    .into_iter()
+                    .zip([TimeRange::from(0..10), TimeRange::from(10..20)]),
+            )
+            .await;
+
+            // Rows with the same primary keys are all returned, ordered by sequence.
+            let result_stream = storage
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                    predicate: vec![],
+                    projections: None,
+                    sample_ratio: None,
+                })
+                .await
+                .unwrap();
+            let result_schema = result_stream.schema();
+            let result = collect(result_stream).await.unwrap();
+            let expected = record_batch!(
+                ("pk1", UInt8, vec![1, 1, 2, 3]),
+                ("v1", Int64, vec![10, 11, 20, 30]),
+                ("v2", Int64, vec![100, 101, 200, 300])
+            )
+            .unwrap();
+            assert_eq!(expected, concat_batches(&result_schema, &result).unwrap());
+
+            let result_stream = storage
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                    predicate: vec![col("v2").gt_eq(lit(101_i64))],
+                    projections: Some(vec![1]),
+                    sample_ratio: None,
+                })
+                .await
+                .unwrap();
+            let result_schema = result_stream.schema();
+            let result = collect(result_stream).await.unwrap();
+            let expected = record_batch!(("v1", Int64, vec![11, 20, 30])).unwrap();
+            assert_eq!(expected, concat_batches(&result_schema, &result).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_storage_append_mode_requires_binary_values() {
+        let schema = arrow_schema!(("pk1", UInt8), ("value", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let res = build_storage(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig {
+                    update_mode: UpdateMode::Append,
+                    ..Default::default()
+                },
+            )
+            .await;
+            assert!(res.is_err());
+        });
+    }
+
+    #[test]
+    fn test_storage_rejects_zero_write_sizes() {
+        let schema = arrow_schema!(("pk1", UInt8), ("value", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let writes = [
+                WriteConfig {
+                    max_row_group_size: 0,
+                    ..Default::default()
+                },
+                WriteConfig {
+                    data_page_size_limit: 0,
+                    ..Default::default()
+                },
+            ];
+            for write in writes {
+                let res = build_storage(
+                    root_dir.path().to_string_lossy().to_string(),
+                    runtimes.clone(),
+                    schema.clone(),
+                    1, // num_primary_keys
+                    StorageConfig {
+                        write,
+                        ..Default::default()
+                    },
+                )
+                .await;
+                assert!(res.is_err());
+            }
+        });
+    }
+
+    #[test(test)]
+    fn test_storage_byte_stream_split_encoding() {
+        let schema = arrow_schema!(("pk1", UInt8), ("value", Float64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let batch = record_batch!(
+                ("pk1", UInt8, vec![3, 1, 2]),
+                ("value", Float64, vec![3.5, 1.25, -2.0])
+            )
+            .unwrap();
+            let storage = build_storage_with_data(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig {
+                    write: WriteConfig {
+                        column_options: Some(HashMap::from([(
+                            "value".to_string(),
+                            ColumnOptions {
+                                encoding: Some(ParquetEncoding::ByteStreamSplit),
+                                ..Default::default()
+                            },
+                        )])),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                [(batch, TimeRange::from(1..10))],
+            )
+            .await;
+
+            let result_stream = storage
+                .scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                    predicate: vec![],
+                    projections: None,
+                    sample_ratio: None,
+                })
+                .await
+                .unwrap();
+            let result_schema = result_stream.schema();
+            let result = collect(result_stream).await.unwrap();
+            let expected = record_batch!(
+                ("pk1", UInt8, vec![1, 2, 3]),
+                ("value", Float64, vec![1.25, -2.0, 3.5])
+            )
+            .unwrap();
+            assert_eq!(expected, concat_batches(&result_schema, &result).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_storage_byte_stream_split_rejects_unsupported_types() {
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let dict_type =
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+            let list_type = DataType::new_list(DataType::Int64, true);
+            for (value_type, update_mode) in [
+                (DataType::Binary, UpdateMode::Append),
+                (dict_type, UpdateMode::Overwrite),
+                (list_type, UpdateMode::Overwrite),
+            ] {
+                let schema = Arc::new(Schema::new(vec![
+                    Field::new("pk1", DataType::UInt8, true),
+                    Field::new("value", value_type, true),
+                ]));
+                let root_dir = temp_dir::TempDir::new().unwrap();
+                let res = build_storage(
+                    root_dir.path().to_string_lossy().to_string(),
+                    runtimes.clone(),
+                    schema,
+                    1, // num_primary_keys
+                    StorageConfig {
+                        write: WriteConfig {
+                            encoding: ParquetEncoding::ByteStreamSplit,
+                            ..Default::default()
+                        },
+                        update_mode,
+                        ..Default::default()
+                    },
+                )
+                .await;
+                assert!(res.is_err());
+            }
+        });
+    }
+
+    #[test]
+    fn test_storage_build_write_props() {
+        let write = WriteConfig {
+            data_page_size_limit: 4096,
+            statistics: ParquetStatistics::Chunk,
+            column_options: Some(HashMap::from([(
+                "value".to_string(),
+                ColumnOptions {
+                    statistics: Some(ParquetStatistics::None),
+                    ..Default::default()
+                },
+            )])),
+            ..Default::default()
+        };
+        let props = CloudObjectStorage::build_write_props(write, 1);
+        assert_eq!(4096, props.data_page_size_limit());
+        assert_eq!(
+            EnabledStatistics::Chunk,
+            props.statistics_enabled(&ColumnPath::from("pk1"))
+        );
+        assert_eq!(
+            EnabledStatistics::None,
+            props.statistics_enabled(&ColumnPath::from("value"))
+        );
+    }
+
+    #[test]
+    fn test_storage_size_quota() {
+        let schema = arrow_schema!(("pk1", UInt8), ("value", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let storage = build_storage(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig {
+                    size_quota: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+            let write = || async {
+                let batch = record_batch!(("pk1", UInt8, vec![1, 2]), ("value", Int64, vec![3, 4]))
+                    .unwrap();
+                storage
+                    .write(WriteRequest {
+                        batch,
+                        time_range: (1..10).into(),
+                        enable_check: true,
+                    })
+                    .await
+            };
+            // No data is written yet, so the first write is accepted.
+            write().await.unwrap();
+            assert!(write().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_storage_scan_limits() {
+        let schema = arrow_schema!(("pk1", UInt8), ("value", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let storage = build_storage(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig {
+                    read: ReadConfig {
+                        max_scan_time_range: Some(Duration::from_hours(1)),
+                        max_scan_bytes: Some(1),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+            let scan = |range: TimeRange| {
+                storage.scan(ScanRequest {
+                    range,
+                    predicate: vec![],
+                    projections: None,
+                    sample_ratio: None,
+                })
+            };
+            // No SST to read yet.
+            assert!(scan((0..10).into()).await.is_ok());
+            assert!(scan(TimeRange::new(Timestamp(0), Timestamp::MAX))
+                .await
+                .is_err());
+
+            let batch =
+                record_batch!(("pk1", UInt8, vec![1, 2]), ("value", Int64, vec![3, 4])).unwrap();
+            storage
+                .write(WriteRequest {
+                    batch,
+                    time_range: (1..10).into(),
+                    enable_check: true,
+                })
+                .await
+                .unwrap();
+            assert!(scan((0..10).into()).await.is_err());
+        });
+    }
+
+    /// Creates storage with two SSTs, covering `1..10` and `10..20`.
+    async fn build_storage_for_purge(
+        root_dir: String,
+        runtimes: StorageRuntimes,
+    ) -> CloudObjectStorage {
+        let batch =
+            record_batch!(("pk1", UInt8, vec![1, 2]), ("value", Int64, vec![3, 4])).unwrap();
+        build_storage_with_data(
+            root_dir,
+            runtimes,
+            arrow_schema!(("pk1", UInt8), ("value", Int64)),
+            1, // num_primary_keys
+            StorageConfig::default(),
+            [
+                (batch.clone(), TimeRange::from(1..10)),
+                (batch, TimeRange::from(10..20)),
+            ],
+        )
+        .await
+    }
+
+    #[test]
+    fn test_storage_purge_before() {
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let storage =
+                build_storage_for_purge(root_dir.path().to_string_lossy().to_string(), runtimes)
+                    .await;
+
+            // Second SST is only partially before cutoff.
+            assert_eq!(1, storage.purge_before(Timestamp(15)).await.unwrap());
+            assert_eq!(1, storage.list_ssts().await.unwrap().len());
+            assert_eq!(
+                Some(TimeRange::from(10..20)),
+                storage.time_range().await.unwrap()
+            );
+            assert_eq!(0, storage.purge_before(Timestamp(15)).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_storage_purge_skips_compacting_ssts() {
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let storage =
+                build_storage_for_purge(root_dir.path().to_string_lossy().to_string(), runtimes)
+                    .await;
+
+            // Simulate a running compaction task owning the first SST.
+            let compacting = storage
+                .list_ssts()
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|f| f.meta().time_range == TimeRange::from(1..10))
+                .unwrap();
+            assert!(compacting.try_mark_compaction());
+
+            assert_eq!(1, storage.purge_before(Timestamp(20)).await.unwrap());
+            let ssts = storage.list_ssts().await.unwrap();
+            assert_eq!(1, ssts.len());
+            assert_eq!(compacting.id(), ssts[0].id());
+            assert!(ssts[0].is_compaction());
+        });
+    }
+
+    #[test]
+    fn test_storage_scan_after_purge() {
+        let schema = arrow_schema!(("pk1", UInt8), ("value", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let root_dir = root_dir.path().to_string_lossy().to_string();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            let batches = [
+                (
+                    record_batch!(("pk1", UInt8, vec![1]), ("value", Int64, vec![1])).unwrap(),
+                    TimeRange::from(1..10),
+                ),
+                (
+                    record_batch!(("pk1", UInt8, vec![2]), ("value", Int64, vec![2])).unwrap(),
+                    TimeRange::from(10..20),
+                ),
+            ];
+            let storage = build_storage_with_data(
+                root_dir.clone(),
+                runtimes.clone(),
+                schema.clone(),
+                1, // num_primary_keys
+                StorageConfig::default(),
+                batches,
+            )
+            .await;
+
+            // Simulate a running compaction task owning the first SST, so it's
+            // not removed by purge.
+            let compacting = storage
+                .list_ssts()
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|f| f.meta().time_range == TimeRange::from(1..10))
+                .unwrap();
+            assert!(compacting.try_mark_compaction());
+            assert_eq!(0, storage.purge_before(Timestamp(10)).await.unwrap());
+            // Earlier cutoff doesn't make purged data visible again.
+            assert_eq!(0, storage.purge_before(Timestamp(5)).await.unwrap());
+
+            let expected =
+                record_batch!(("pk1", UInt8, vec![2]), ("value", Int64, vec![2])).unwrap();
+            let scan_request = |range: TimeRange| ScanRequest {
+                range,
+                predicate: vec![],
+                projections: None,
+                sample_ratio: None,
+            };
+            let result_stream = storage
+                .scan(scan_request(TimeRange::new(Timestamp(0), Timestamp::MAX)))
+                .await
+                .unwrap();
+            check_stream(result_stream, [expected.clone()]).await;
+            let result_stream = storage
+                .scan(scan_request(TimeRange::new(Timestamp(0), Timestamp(10))))
+                .await
+                .unwrap();
+            check_stream(result_stream, Vec::<RecordBatch>::new()).await;
+
+            // Cutoff is persisted, so it still applies after reopen.
+            drop(storage);
+            let storage = build_storage(
+                root_dir,
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(2, storage.list_ssts().await.unwrap().len());
+            let result_stream = storage
+                .scan(scan_request(TimeRange::new(Timestamp(0), Timestamp::MAX)))
+                .await
+                .unwrap();
+            check_stream(result_stream, [expected]).await;
+        });
+    }
+
+    #[test]
+    fn test_storage_scan_sample() {
+        let schema = arrow_schema!(("pk1", UInt8), ("value", Int64));
+        let root_dir = temp_dir::TempDir::new().unwrap();
+        let runtimes = build_runtimes();
+        runtimes.sst_compact_runtime.clone().block_on(async move {
+            // One row with distinct primary key per SST.
+            let batches = (0..8).map(|i| {
+                let batch = record_batch!(("pk1", UInt8, vec![i as u8]), ("value", Int64, vec![i]))
+                    .unwrap();
+                (batch, TimeRange::from(i..i + 1))
+            });
+            let storage = build_storage_with_data(
+                root_dir.path().to_string_lossy().to_string(),
+                runtimes,
+                schema,
+                1, // num_primary_keys
+                StorageConfig::default(),
+                batches,
+            )
+            .await;
+
+            let scan = |sample_ratio: Option<f64>| {
+                storage.scan(ScanRequest {
+                    range: TimeRange::new(Timestamp(0), Timestamp::MAX),
+                    predicate: vec![],
+                    projections: None,
+                    sample_ratio,
+                })
+            };
+            for (ratio, expected_rows) in [
+                (None, 8),
+                (Some(1.0), 8),
+                (Some(0.75), 6),
+                (Some(0.25), 2),
+                (Some(0.01), 1),
+            ] {
+                let num_rows = scan(ratio)
+                    .await
+                    .unwrap()
+                    .map(|batch| batch.unwrap().num_rows())
+                    .collect::<Vec<_>>()
+                    .await
+                    .into_iter()
+                    .sum::<usize>();
+                assert_eq!(expected_rows, num_rows, "ratio:{ratio:?}");
+            }
+            for ratio in [0.0, 1.5, f64::NAN] {
+                assert!(scan(Some(ratio)).await.is_err(), "ratio:{ratio}");
+            }
         });
     }
 
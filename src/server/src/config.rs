@@ -22,6 +22,9 @@ use serde::{Deserialize, Serialize};
 #[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub port: u16,
+    /// Max time to wait for in-flight requests and background writes when
+    /// shutting down, rounded up to seconds for requests.
+    pub shutdown_timeout: ReadableDuration,
     pub test: TestConfig, // for test
     pub metric_engine: MetricEngineConfig,
 }
@@ -30,6 +33,7 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             port: 5000,
+            shutdown_timeout: ReadableDuration::secs(30),
             test: TestConfig::default(),
             metric_engine: MetricEngineConfig::default(),
         }
@@ -18,11 +18,12 @@
 #![feature(duration_constructors)]
 mod config;
 use std::{
+    collections::BTreeMap,
     fs,
     iter::repeat_with,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, OnceLock,
     },
     time::Duration,
 };
@@ -38,13 +39,17 @@ use arrow::{
 };
 use clap::Parser;
 use config::{Config, ObjectStorageConfig};
+use futures::future::join_all;
 use metric_engine::{
+    manifest::{PREFIX_PATH, SNAPSHOT_FILENAME},
     storage::{
         CloudObjectStorage, CompactRequest, StorageRuntimes, TimeMergeStorageRef, WriteRequest,
     },
-    types::RuntimeRef,
+    types::{ObjectStoreRef, RuntimeRef},
 };
-use object_store::local::LocalFileSystem;
+use object_store::{local::LocalFileSystem, path::Path, ObjectStore};
+use serde::Serialize;
+use tokio::{task::JoinHandle, time::Instant};
 use tracing::{error, info};
 use tracing_subscriber::EnvFilter;
 
@@ -71,6 +76,82 @@ async fn toggle(data: web::Data<AppState>) -> impl Responder {
     }
 }
 
+#[get("/health/live")]
+async fn live() -> impl Responder {
+    HttpResponse::Ok().body("OK")
+}
+
+#[get("/health/ready")]
+async fn ready(data: web::Data<AppState>) -> impl Responder {
+    // Storage is opened before http server starts, so only check whether object
+    // store is reachable here, by a read-only head of the manifest snapshot. The
+    // snapshot is absent until first merged, which also means it's reachable.
+    let snapshot_path = Path::from(format!(
+        "{}/{PREFIX_PATH}/{SNAPSHOT_FILENAME}",
+        data.data_dir
+    ));
+    let res = match data.store.head(&snapshot_path).await {
+        Err(object_store::Error::NotFound { .. }) => Ok(()),
+        res => res.map(|_| ()),
+    };
+    match res {
+        Ok(_) => HttpResponse::Ok().body("OK"),
+        Err(e) => {
+            HttpResponse::ServiceUnavailable().body(format!("object store is unavailable, err:{e}"))
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SstLayout {
+    id: u64,
+    start: i64,
+    end: i64,
+    num_rows: u32,
+    size: u32,
+    in_compaction: bool,
+}
+
+#[derive(Serialize)]
+struct SegmentLayout {
+    start: i64,
+    ssts: Vec<SstLayout>,
+}
+
+/// Returns SSTs grouped by segment, used to visualize the storage layout.
+#[get("/layout")]
+async fn layout(data: web::Data<AppState>) -> impl Responder {
+    let ssts = match data.storage.list_ssts().await {
+        Ok(v) => v,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("list ssts failed, err:{e}"))
+        }
+    };
+
+    let mut ssts_by_segment = BTreeMap::new();
+    for sst in ssts {
+        let time_range = &sst.meta().time_range;
+        let segment = time_range.start.truncate_by(data.segment_duration);
+        ssts_by_segment
+            .entry(*segment)
+            .or_insert_with(Vec::new)
+            .push(SstLayout {
+                id: sst.id(),
+                start: *time_range.start,
+                end: *time_range.end,
+                num_rows: sst.meta().num_rows,
+                size: sst.meta().size,
+                in_compaction: sst.is_compaction(),
+            });
+    }
+    let segments = ssts_by_segment
+        .into_iter()
+        .map(|(start, ssts)| SegmentLayout { start, ssts })
+        .collect::<Vec<_>>();
+
+    HttpResponse::Ok().json(segments)
+}
+
 #[get("/compact")]
 async fn compact(data: web::Data<AppState>) -> impl Responder {
     if let Err(e) = data.storage.compact(CompactRequest::default()).await {
@@ -81,6 +162,9 @@ async fn compact(data: web::Data<AppState>) -> impl Responder {
 
 struct AppState {
     storage: TimeMergeStorageRef,
+    store: ObjectStoreRef,
+    data_dir: String,
+    segment_duration: Duration,
     keep_writing: Arc<AtomicBool>,
 }
 
@@ -99,6 +183,7 @@ pub fn main() {
     info!("Config loaded: \n{:#?}", config);
 
     let port = config.port;
+    let shutdown_timeout = config.shutdown_timeout.0;
     let rt = build_multi_runtime("main", 1);
     let manifest_compact_runtime = build_multi_runtime(
         "manifest-compact",
@@ -106,7 +191,12 @@ pub fn main() {
     );
     let sst_compact_runtime =
         build_multi_runtime("sst-compact", config.metric_engine.threads.sst_thread_num);
-    let runtimes = StorageRuntimes::new(manifest_compact_runtime, sst_compact_runtime);
+    // Runtimes are also kept here, so they are dropped after `block_on` returns,
+    // dropping a runtime in async context panics.
+    let runtimes = StorageRuntimes::new(
+        manifest_compact_runtime.clone(),
+        sst_compact_runtime.clone(),
+    );
     let object_store_config = match config.metric_engine.storage.object_store {
         ObjectStorageConfig::Local(v) => v,
         ObjectStorageConfig::S3Like(_) => panic!("S3 not support yet"),
@@ -117,14 +207,17 @@ pub fn main() {
     let segment_duration = config.test.segment_duration.0;
     let enable_write = config.test.enable_write;
     let write_rt = build_multi_runtime("write", write_worker_num);
+    let bench_write_rt = write_rt.clone();
     let keep_writing = Arc::new(AtomicBool::new(true));
+    let stop_writing = Arc::new(AtomicBool::new(false));
     let _ = rt.block_on(async move {
-        let store = Arc::new(LocalFileSystem::new());
+        let store: ObjectStoreRef = Arc::new(LocalFileSystem::new());
+        let data_dir = object_store_config.data_dir;
         let storage = Arc::new(
             CloudObjectStorage::try_new(
-                object_store_config.data_dir,
+                data_dir.clone(),
                 segment_duration,
-                store,
+                store.clone(),
                 build_schema(),
                 3,
                 time_merge_storage_config,
@@ -134,36 +227,103 @@ pub fn main() {
             .unwrap(),
         );
 
-        if enable_write {
+        let write_handles = if enable_write {
             bench_write(
                 storage.clone(),
-                write_rt.clone(),
+                bench_write_rt,
                 write_worker_num,
                 write_interval,
                 keep_writing.clone(),
-            );
-        }
+                stop_writing.clone(),
+            )
+        } else {
+            Vec::new()
+        };
 
         let app_state = Data::new(AppState {
             storage,
-            keep_writing,
+            store,
+            data_dir,
+            segment_duration,
+            keep_writing: keep_writing.clone(),
         });
         info!(port, "Start HoraeDB http server...");
-        HttpServer::new(move || {
+        let server = HttpServer::new(move || {
             App::new()
                 .app_data(app_state.clone())
                 .service(hello)
+                .service(live)
+                .service(ready)
                 .service(compact)
+                .service(layout)
                 .service(toggle)
         })
         .workers(4)
+        // Signals are handled below, so the shutdown deadline starts when
+        // signal is received.
+        .disable_signals()
+        // Round up, so sub-second timeout won't become 0.
+        .shutdown_timeout(shutdown_timeout.as_secs_f64().ceil() as u64)
         .bind(("127.0.0.1", port))
         .expect("Server bind failed")
-        .run()
-        .await
+        .run();
+
+        // In-flight requests and background writes are waited concurrently,
+        // under the same deadline.
+        let shutdown_deadline = Arc::new(OnceLock::new());
+        let server_handle = server.handle();
+        {
+            let shutdown_deadline = shutdown_deadline.clone();
+            let stop_writing = stop_writing.clone();
+            tokio::spawn(async move {
+                wait_for_shutdown_signal().await;
+                shutdown_deadline.get_or_init(|| Instant::now() + shutdown_timeout);
+                stop_writing.store(true, Ordering::Relaxed);
+                server_handle.stop(true).await;
+            });
+        }
+        let res = server.await;
+
+        // Wait for in-flight writes, otherwise a write may leave an SST not
+        // recorded in manifest. Running compactions are not waited for, they
+        // update manifest before deleting input SSTs, so an interrupted one
+        // only leaves an unreferenced output SST.
+        let deadline = *shutdown_deadline.get_or_init(|| Instant::now() + shutdown_timeout);
+        stop_writing.store(true, Ordering::Relaxed);
+        if tokio::time::timeout_at(deadline, join_all(write_handles))
+            .await
+            .is_err()
+        {
+            error!("Background writes not finished before shutdown timeout");
+        }
+        info!("HoraeDB http server stopped");
+        res
     });
 }
 
+/// Waits for SIGINT, or SIGTERM on unix.
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("register SIGTERM handler")
+            .recv()
+            .await
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<Option<()>>();
+
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => {
+            if let Err(e) = res {
+                error!("Listen for ctrl-c failed, err:{e}");
+            }
+        }
+        _ = terminate => {}
+    }
+    info!("Shutdown signal received");
+}
+
 fn build_multi_runtime(name: &str, workers: usize) -> RuntimeRef {
     let rt = tokio::runtime::Builder::new_multi_thread()
         .thread_name(name)
@@ -190,20 +350,26 @@ fn bench_write(
     workers: usize,
     interval: Duration,
     keep_writing: Arc<AtomicBool>,
-) {
+    stop_writing: Arc<AtomicBool>,
+) -> Vec<JoinHandle<()>> {
     let schema = Arc::new(Schema::new(vec![
         Field::new("pk1", DataType::Int64, true),
         Field::new("pk2", DataType::Int64, true),
         Field::new("pk3", DataType::Int64, true),
         Field::new("value", DataType::Int64, true),
     ]));
+    let mut handles = Vec::with_capacity(workers);
     for _ in 0..workers {
         let storage = storage.clone();
         let schema = schema.clone();
         let keep_writing = keep_writing.clone();
-        rt.spawn(async move {
+        let stop_writing = stop_writing.clone();
+        let handle = rt.spawn(async move {
             loop {
                 tokio::time::sleep(interval).await;
+                if stop_writing.load(Ordering::Relaxed) {
+                    break;
+                }
                 if !keep_writing.load(Ordering::Relaxed) {
                     continue;
                 }
@@ -229,5 +395,8 @@ fn bench_write(
                 }
             }
         });
+        handles.push(handle);
     }
+
+    handles
 }